@@ -8,21 +8,39 @@
 #import shadplay::shader_utils::common NEG_HALF_PI, shader_toy_default, rotate2D
 
 
-#import bevy_sprite::mesh2d_view_bindings globals 
-
 #import bevy_render::view  View
 @group(0) @binding(0) var<uniform> view: View;
 
+// see `ParallaxUniform`
+struct ParallaxLayer {
+    offset: vec2<f32>,
+    size: vec2<f32>,
+}
+@group(1) @binding(0) var<uniform> layer: ParallaxLayer;
+
+// see `BackgroundUniform`
+struct Background {
+    time: f32,
+    danger: f32,
+    resolution: vec2<f32>,
+    camera: vec2<f32>,
+    player: vec2<f32>,
+}
+@group(1) @binding(1) var<uniform> background: Background;
+
+// how much faster the colors cycle when an enemy is close
+const DANGER_SPEED: f32 = 3.0;
+
 const SPEED:f32 = 1.0; 
 
 // This is a port of the default shader you get from in www.shadertoy.com/new
 fn shadertoy_default(uv: vec2<f32>) -> vec4<f32> {
     var uv = uv;
-    let t = globals.time;
+    let t = background.time;
     uv *= 3.1459;
 
     let temp: vec3<f32> = uv.xyx + vec3<f32>(0.0, 2.0, 4.0);
-    let cos_val: vec3<f32> = cos(globals.time + temp);
+    let cos_val: vec3<f32> = cos(background.time + temp);
     let col: vec3<f32> = vec3<f32>(0.5) + vec3<f32>(0.5) * cos_val;
 
     return vec4<f32>(col, 1.0);
@@ -32,11 +50,22 @@ fn shadertoy_default(uv: vec2<f32>) -> vec4<f32> {
 fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
     // ensure our uv coords match shadertoy/the-lil-book-of-shaders
     var uv = (in.uv * 2.0) - 1.0;
-    let resolution = view.viewport.zw;
-    let t = globals.time * SPEED;
+    let resolution = background.resolution;
+    let t = background.time * SPEED * (1.0 + background.danger * DANGER_SPEED);
     uv.x *= resolution.x / resolution.y;
+
+    // light up the background around the player, in the same units as the uv
+    let player = (background.player - background.camera) / layer.size.y * 2.0;
+    let light = 1.0 - smoothstep(0.0, 1.5, distance(uv, vec2(player.x, -player.y)));
+
+    // scroll by the layer's offset
+    uv += layer.offset / layer.size.y * 2.0;
     uv *= rotate2D(NEG_HALF_PI);
 
-    return vec4f(shader_toy_default(t, uv), 1.0);
+    var color = shader_toy_default(t, uv) * (0.6 + 0.4 * light);
+    // turn red as enemies come close
+    color = mix(color, vec3(color.r, color.g * 0.3, color.b * 0.3), background.danger);
+
+    return vec4f(color, 1.0);
 }    
     
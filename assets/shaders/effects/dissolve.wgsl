@@ -0,0 +1,52 @@
+// Draws the sprite's frame with holes that grow until it's gone, see `DissolveMaterial`
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+
+// see `SpriteEffectUniform`
+struct SpriteEffect {
+    uv_min: vec2<f32>,
+    uv_max: vec2<f32>,
+    color: vec4<f32>,
+    amount: f32,
+}
+@group(1) @binding(0) var<uniform> effect: SpriteEffect;
+@group(1) @binding(1) var texture: texture_2d<f32>;
+@group(1) @binding(2) var texture_sampler: sampler;
+
+// how wide the glowing edge of the holes is
+const EDGE: f32 = 0.1;
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// value noise on a grid of `cells` per frame
+fn noise(uv: vec2<f32>, cells: f32) -> f32 {
+    let p = uv * cells;
+    let i = floor(p);
+    let f = smoothstep(vec2(0.0), vec2(1.0), fract(p));
+
+    let a = hash(i);
+    let b = hash(i + vec2(1.0, 0.0));
+    let c = hash(i + vec2(0.0, 1.0));
+    let d = hash(i + vec2(1.0, 1.0));
+
+    return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    let uv = mix(effect.uv_min, effect.uv_max, in.uv);
+    let sprite = textureSample(texture, texture_sampler, uv);
+
+    // the noise follows the quad, not the atlas, so the holes don't jump between frames
+    let n = noise(in.uv, 6.0) * 0.7 + noise(in.uv, 16.0) * 0.3;
+    if n < effect.amount {
+        discard;
+    }
+
+    let edge = 1.0 - smoothstep(0.0, EDGE, n - effect.amount);
+    let color = mix(sprite.rgb, effect.color.rgb, edge * step(0.001, effect.amount));
+
+    return vec4(color, sprite.a);
+}
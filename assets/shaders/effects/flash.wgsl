@@ -0,0 +1,22 @@
+// Draws the sprite's frame in a color, see `FlashMaterial`
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+
+// see `SpriteEffectUniform`
+struct SpriteEffect {
+    uv_min: vec2<f32>,
+    uv_max: vec2<f32>,
+    color: vec4<f32>,
+    amount: f32,
+}
+@group(1) @binding(0) var<uniform> effect: SpriteEffect;
+@group(1) @binding(1) var texture: texture_2d<f32>;
+@group(1) @binding(2) var texture_sampler: sampler;
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    let uv = mix(effect.uv_min, effect.uv_max, in.uv);
+    let alpha = textureSample(texture, texture_sampler, uv).a;
+
+    return vec4(effect.color.rgb, alpha * effect.color.a * effect.amount);
+}
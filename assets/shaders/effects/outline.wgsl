@@ -0,0 +1,41 @@
+// Draws an outline around the sprite's frame, see `OutlineMaterial`
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+
+// see `SpriteEffectUniform`
+struct SpriteEffect {
+    uv_min: vec2<f32>,
+    uv_max: vec2<f32>,
+    color: vec4<f32>,
+    amount: f32,
+}
+@group(1) @binding(0) var<uniform> effect: SpriteEffect;
+@group(1) @binding(1) var texture: texture_2d<f32>;
+@group(1) @binding(2) var texture_sampler: sampler;
+
+// the alpha of the frame at `uv`, transparent outside of the frame
+fn frame_alpha(uv: vec2<f32>) -> f32 {
+    let low = min(effect.uv_min, effect.uv_max);
+    let high = max(effect.uv_min, effect.uv_max);
+    if any(uv < low) || any(uv > high) {
+        return 0.0;
+    }
+    return textureSample(texture, texture_sampler, uv).a;
+}
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    let uv = mix(effect.uv_min, effect.uv_max, in.uv);
+    let texel = effect.amount / vec2<f32>(textureDimensions(texture));
+
+    // transparent texels next to opaque ones are the outline
+    var neighbours = 0.0;
+    neighbours = max(neighbours, frame_alpha(uv + vec2(texel.x, 0.0)));
+    neighbours = max(neighbours, frame_alpha(uv - vec2(texel.x, 0.0)));
+    neighbours = max(neighbours, frame_alpha(uv + vec2(0.0, texel.y)));
+    neighbours = max(neighbours, frame_alpha(uv - vec2(0.0, texel.y)));
+
+    let outline = neighbours * (1.0 - frame_alpha(uv));
+
+    return vec4(effect.color.rgb, effect.color.a * outline);
+}
@@ -0,0 +1,22 @@
+// A texture that repeats in every direction, scrolled by its parallax layer
+
+#import bevy_pbr::mesh_vertex_output MeshVertexOutput
+
+// see `ParallaxUniform`
+struct ParallaxLayer {
+    offset: vec2<f32>,
+    size: vec2<f32>,
+}
+@group(1) @binding(0) var<uniform> layer: ParallaxLayer;
+@group(1) @binding(1) var texture: texture_2d<f32>;
+@group(1) @binding(2) var texture_sampler: sampler;
+
+@fragment
+fn fragment(in: MeshVertexOutput) -> @location(0) vec4<f32> {
+    // the uv's y points down, the world's up
+    let position = (vec2(in.uv.x, 1.0 - in.uv.y) - 0.5) * layer.size + layer.offset;
+    let dimensions = vec2<f32>(textureDimensions(texture));
+    let uv = fract(vec2(position.x, -position.y) / dimensions);
+
+    return textureSample(texture, texture_sampler, uv);
+}
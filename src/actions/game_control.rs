@@ -1,35 +1,146 @@
-use bevy::prelude::{Input, KeyCode, Res};
+use bevy::prelude::{
+    Axis, Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Input, KeyCode,
+    Res, Vec2,
+};
+
+/// How far a stick has to be pushed before it counts as a direction
+const STICK_DEAD_ZONE: f32 = 0.2;
 
 pub enum GameControl {
     Up,
     Down,
     Left,
     Right,
+    Jump,
+    Interact,
+}
+
+/// The keys one player controls their character with
+pub struct KeyBindings {
+    pub up: &'static [KeyCode],
+    pub down: &'static [KeyCode],
+    pub left: &'static [KeyCode],
+    pub right: &'static [KeyCode],
+    pub jump: &'static [KeyCode],
+    pub interact: &'static [KeyCode],
+}
+
+/// A single player may use both halves of the keyboard
+pub const SOLO_KEYS: KeyBindings = KeyBindings {
+    up: &[KeyCode::W, KeyCode::Up],
+    down: &[KeyCode::S, KeyCode::Down],
+    left: &[KeyCode::A, KeyCode::Left],
+    right: &[KeyCode::D, KeyCode::Right],
+    jump: &[KeyCode::Space],
+    interact: &[KeyCode::E],
+};
+
+/// The first of two players sharing the keyboard
+pub const LEFT_KEYS: KeyBindings = KeyBindings {
+    up: &[KeyCode::W],
+    down: &[KeyCode::S],
+    left: &[KeyCode::A],
+    right: &[KeyCode::D],
+    jump: &[KeyCode::Space],
+    interact: &[KeyCode::E],
+};
+
+/// The second of two players sharing the keyboard
+pub const RIGHT_KEYS: KeyBindings = KeyBindings {
+    up: &[KeyCode::Up],
+    down: &[KeyCode::Down],
+    left: &[KeyCode::Left],
+    right: &[KeyCode::Right],
+    jump: &[KeyCode::ShiftRight],
+    interact: &[KeyCode::Return],
+};
+
+impl KeyBindings {
+    fn keys(&self, control: &GameControl) -> &'static [KeyCode] {
+        match control {
+            GameControl::Up => self.up,
+            GameControl::Down => self.down,
+            GameControl::Left => self.left,
+            GameControl::Right => self.right,
+            GameControl::Jump => self.jump,
+            GameControl::Interact => self.interact,
+        }
+    }
 }
 
 impl GameControl {
-    pub fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    pub fn pressed(&self, keys: &KeyBindings, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        keyboard_input.any_pressed(keys.keys(self).iter().copied())
+    }
+
+    pub fn just_pressed(&self, keys: &KeyBindings, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        keyboard_input.any_just_pressed(keys.keys(self).iter().copied())
+    }
+
+    fn gamepad_buttons(&self) -> &'static [GamepadButtonType] {
         match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
+            GameControl::Up => &[GamepadButtonType::DPadUp],
+            GameControl::Down => &[GamepadButtonType::DPadDown],
+            GameControl::Left => &[GamepadButtonType::DPadLeft],
+            GameControl::Right => &[GamepadButtonType::DPadRight],
+            GameControl::Jump => &[GamepadButtonType::South],
+            GameControl::Interact => &[GamepadButtonType::West],
         }
     }
+
+    pub fn gamepad_just_pressed(
+        &self,
+        gamepad: Gamepad,
+        button_input: &Res<Input<GamepadButton>>,
+    ) -> bool {
+        button_input.any_just_pressed(
+            self.gamepad_buttons()
+                .iter()
+                .map(|button_type| GamepadButton::new(gamepad, *button_type)),
+        )
+    }
 }
 
-pub fn get_movement(control: GameControl, input: &Res<Input<KeyCode>>) -> f32 {
-    if control.pressed(input) {
+pub fn get_movement(control: GameControl, keys: &KeyBindings, input: &Res<Input<KeyCode>>) -> f32 {
+    if control.pressed(keys, input) {
         1.0
     } else {
         0.0
     }
 }
+
+/// The direction of the left stick, or of the d-pad if the stick is at rest
+pub fn get_gamepad_movement(
+    gamepad: Gamepad,
+    button_input: &Res<Input<GamepadButton>>,
+    axes: &Res<Axis<GamepadAxis>>,
+) -> Vec2 {
+    let stick = Vec2::new(
+        axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or_default(),
+        axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or_default(),
+    );
+
+    if stick.length() > STICK_DEAD_ZONE {
+        return stick;
+    }
+
+    let pressed = |control: GameControl| {
+        let buttons = control.gamepad_buttons();
+        if button_input.any_pressed(
+            buttons
+                .iter()
+                .map(|button_type| GamepadButton::new(gamepad, *button_type)),
+        ) {
+            1.0
+        } else {
+            0.0
+        }
+    };
+
+    Vec2::new(
+        pressed(GameControl::Right) - pressed(GameControl::Left),
+        pressed(GameControl::Up) - pressed(GameControl::Down),
+    )
+}
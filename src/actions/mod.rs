@@ -1,8 +1,15 @@
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
-use crate::actions::game_control::{get_movement, GameControl};
-use crate::player::Player;
+use crate::actions::game_control::{
+    get_gamepad_movement, get_movement, GameControl, KeyBindings, LEFT_KEYS, RIGHT_KEYS, SOLO_KEYS,
+};
+use crate::camera::{
+    pixel_perfect::{window_to_target, UpscaleSprite},
+    MainCamera,
+};
+use crate::player::{LocalPlayers, Player};
+use crate::timestep::FixedGameplaySet;
 use crate::GameState;
 
 mod game_control;
@@ -11,54 +18,125 @@ pub const FOLLOW_EPSILON: f32 = 5.;
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
-// Actions can then be used as a resource in other systems to act on the player input.
+// This plugin listens for keyboard and gamepad input and converts the input into the
+// Actions of each player. Every player entity has its own Actions component.
+// Input is read every frame, while the gameplay systems act on it every fixed tick, so
+// one-shot actions like `jump` stay set until a tick has consumed them.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Actions>().add_systems(
+        app.add_systems(
             Update,
             set_movement_actions.run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            FixedUpdate,
+            consume_one_shot_actions
+                .after(FixedGameplaySet)
+                .run_if(in_state(GameState::Playing)),
         );
     }
 }
 
-#[derive(Default, Resource)]
+#[derive(Default, Component)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
     pub jump: bool,
+    pub drop_down: bool,
+    pub interact: bool,
 }
 
+/// The keys of a player. A single player may use the whole keyboard, two players split
+/// it into a left and a right half.
+fn key_bindings(player: &Player, local_players: &LocalPlayers) -> Option<&'static KeyBindings> {
+    match (local_players.0, player.index) {
+        (1, _) => Some(&SOLO_KEYS),
+        (_, 0) => Some(&LEFT_KEYS),
+        (_, 1) => Some(&RIGHT_KEYS),
+        _ => None,
+    }
+}
+
+/// Fills the Actions of each player from their keys, their gamepad and, for the first
+/// player, touch input. The n-th connected gamepad controls the n-th player.
+#[allow(clippy::too_many_arguments)]
 pub fn set_movement_actions(
-    mut actions: ResMut<Actions>,
+    mut players: Query<(&Player, &mut Actions, &Transform)>,
+    local_players: Res<LocalPlayers>,
     keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_input: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
     touch_input: Res<Touches>,
-    player: Query<&Transform, With<Player>>,
-    camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    upscaled: Query<&Sprite, With<UpscaleSprite>>,
+    windows: Query<&Window>,
 ) {
-    let mut player_movement = Vec2::new(
-        get_movement(GameControl::Right, &keyboard_input)
-            - get_movement(GameControl::Left, &keyboard_input),
-        get_movement(GameControl::Up, &keyboard_input)
-            - get_movement(GameControl::Down, &keyboard_input),
-    );
-
-    if let Some(touch_position) = touch_input.first_pressed_position() {
-        let (camera, camera_transform) = camera.single();
-        if let Some(touch_position) = camera.viewport_to_world_2d(camera_transform, touch_position)
+    for (player, mut actions, transform) in players.iter_mut() {
+        let keys = key_bindings(player, &local_players);
+        let gamepad = gamepads.iter().nth(player.index);
+
+        let mut player_movement = keys.map_or(Vec2::ZERO, |keys| {
+            Vec2::new(
+                get_movement(GameControl::Right, keys, &keyboard_input)
+                    - get_movement(GameControl::Left, keys, &keyboard_input),
+                get_movement(GameControl::Up, keys, &keyboard_input)
+                    - get_movement(GameControl::Down, keys, &keyboard_input),
+            )
+        });
+
+        if let Some(gamepad) = gamepad {
+            player_movement += get_gamepad_movement(gamepad, &button_input, &axes);
+        }
+
+        if let Some(touch_position) = touch_input
+            .first_pressed_position()
+            .filter(|_| player.index == 0)
         {
-            let diff = touch_position - player.single().translation.xy();
-            if diff.length() > FOLLOW_EPSILON {
-                player_movement = diff.normalize();
+            let (camera, camera_transform) = camera.single();
+            // the main camera draws into the low resolution image, not the window
+            if let Some(touch_position) = upscaled
+                .get_single()
+                .ok()
+                .zip(windows.get_single().ok())
+                .and_then(|(sprite, window)| window_to_target(touch_position, window, sprite))
+                .and_then(|position| camera.viewport_to_world_2d(camera_transform, position))
+            {
+                let diff = touch_position - transform.translation.xy();
+                if diff.length() > FOLLOW_EPSILON {
+                    player_movement = diff.normalize();
+                }
             }
         }
-    }
 
-    if player_movement != Vec2::ZERO {
-        actions.player_movement = Some(player_movement.normalize());
-    } else {
-        actions.player_movement = None;
+        if player_movement != Vec2::ZERO {
+            actions.player_movement = Some(player_movement.normalize());
+        } else {
+            actions.player_movement = None;
+        }
+
+        let just_pressed = |control: GameControl| {
+            keys.map_or(false, |keys| control.just_pressed(keys, &keyboard_input))
+                || gamepad.map_or(false, |gamepad| {
+                    control.gamepad_just_pressed(gamepad, &button_input)
+                })
+        };
+
+        // Check for jump input
+        actions.jump |= just_pressed(GameControl::Jump);
+
+        // Check for dropping down through one-way platforms
+        actions.drop_down |= just_pressed(GameControl::Down);
+
+        // Check for interacting, e.g. opening chests
+        actions.interact |= just_pressed(GameControl::Interact);
     }
+}
 
-    // Check for jump input
-    actions.jump = keyboard_input.just_pressed(KeyCode::Space);
+/// Clears the one-shot actions once a fixed tick has acted on them
+pub fn consume_one_shot_actions(mut actions: Query<&mut Actions>) {
+    for mut actions in actions.iter_mut() {
+        actions.jump = false;
+        actions.drop_down = false;
+        actions.interact = false;
+    }
 }
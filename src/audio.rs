@@ -1,4 +1,5 @@
-use crate::enemy::Enemy;
+use crate::enemy::{self, Enemy};
+use crate::level::triggers::{TriggerAction, TriggerEntered};
 use crate::loading::AudioAssets;
 use crate::player::Player;
 use crate::GameState;
@@ -19,7 +20,7 @@ impl Plugin for InternalAudioPlugin {
             .add_systems(OnExit(GameState::Playing), (stop_sound, cleanup).chain())
             .add_systems(
                 Update,
-                attenuate_ticktock.run_if(in_state(GameState::Playing)),
+                (attenuate_ticktock, change_music).run_if(in_state(GameState::Playing)),
             );
     }
 }
@@ -45,6 +46,47 @@ fn start_music(mut commands: Commands, audio_assets: Res<AudioAssets>, audio: Re
     commands.insert_resource(MainMusicLoop(handle));
 }
 
+/// Switches the music loop when the player enters a `ChangeMusic` trigger zone. The new
+/// track starts paused if the current one is paused.
+fn change_music(
+    mut triggers: EventReader<TriggerEntered>,
+    audio_assets: Res<AudioAssets>,
+    audio: Res<Audio>,
+    mut main_music: ResMut<MainMusicLoop>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    for event in triggers.iter() {
+        let Some(TriggerAction::ChangeMusic(track)) = &event.action else {
+            continue;
+        };
+
+        let source = match track.as_str() {
+            "fuzzy" | "fuzzy_loop" => audio_assets.fuzzy_music_loop.clone(),
+            "main" | "main_loop" => audio_assets.main_music_loop.clone(),
+            _ => {
+                warn!("unknown music track {:?}", track);
+                continue;
+            }
+        };
+
+        let mut was_playing = false;
+
+        if let Some(instance) = audio_instances.get_mut(&main_music.0) {
+            was_playing = matches!(instance.state(), PlaybackState::Playing { .. });
+            instance.stop(AudioTween::default());
+        }
+
+        let mut play = audio.play(source);
+        play.looped().with_volume(0.3);
+
+        if !was_playing {
+            play.paused();
+        }
+
+        main_music.0 = play.handle();
+    }
+}
+
 fn stop_sound(
     main_music: Res<MainMusicLoop>,
     ticktock: Res<TickTockLoop>,
@@ -83,28 +125,26 @@ fn start_enemy_sound_fx(mut commands: Commands, audio_assets: Res<AudioAssets>,
     commands.insert_resource(AlarmSoundEffect(alarm_handle));
 }
 
-/// Attenuate the ticktock sound based on the distance between the player and the closest enemy
+/// Attenuate the ticktock sound based on the distance between the closest enemy and the
+/// player it is closest to
 fn attenuate_ticktock(
     player_query: Query<&Position, With<Player>>,
     enemy_query: Query<&Position, With<Enemy>>,
     ticktock: Res<TickTockLoop>,
     mut audio_assets: ResMut<Assets<AudioInstance>>,
 ) {
-    const MAX_DISTANCE: f32 = 320.;
     const MAX_VOLUME: f32 = 0.3;
 
-    let player_position = player_query.single();
-    let mut distances = Vec::<f32>::new();
-
-    for enemy_position in enemy_query.iter() {
-        distances.push(enemy_position.distance(player_position.0));
-    }
-
-    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let shortest_distance = distances[0];
-
-    let volume = ((MAX_DISTANCE - shortest_distance) / MAX_DISTANCE).clamp(0.0, 1.0) * MAX_VOLUME;
+    let volume = player_query
+        .iter()
+        .map(|player_position| {
+            enemy::danger_level(
+                player_position.0,
+                enemy_query.iter().map(|enemy_position| enemy_position.0),
+            )
+        })
+        .fold(0.0, f32::max)
+        * MAX_VOLUME;
 
     if let Some(instance) = audio_assets.get_mut(&ticktock.0) {
         instance.set_volume(volume as f64, AudioTween::default());
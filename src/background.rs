@@ -1,78 +1,431 @@
-use crate::{shader_utils::common::ShadplayShaderLibrary, GameState};
+use crate::{
+    camera::{pixel_perfect, BackgroundLayer, CameraFollowSet, MainCamera},
+    enemy::{self, Enemy},
+    level::{fields, rooms::CurrentRoom, PlayedLdtk},
+    player::Player,
+    GameState,
+};
 use bevy::{
     prelude::*,
     reflect::{TypePath, TypeUuid},
-    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
+    render::{
+        render_resource::{AsBindGroup, Shader, ShaderRef, ShaderType},
+        view::RenderLayers,
+    },
     sprite::{Material2d, Material2dPlugin},
+    transform::TransformSystem,
 };
+use bevy_ecs_ldtk::{ldtk::Level, prelude::*};
+use serde::Deserialize;
+
+#[cfg(debug_assertions)]
+use crate::shader_utils::DragNDropShader;
+
+/// The z of the farthest parallax layer, the next ones are in front of it
+const BACKGROUND_Z: f32 = -10.0;
+const LAYER_Z_STEP: f32 = 0.1;
+
+/// The shader of `BackgroundPreview`, replaced by every shader that is dropped onto the
+/// window
+const PREVIEW_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x51a4_0e7d_93c2_6b18);
 
 pub struct BackgroundPlugin;
 
+/// This plugin draws the parallax layers behind the level. The layers of a level are
+/// defined in its multiline String field `parallax`, as a RON list of
+/// `ParallaxLayerConfig`s:
+///
+/// ```ron
+/// [
+///     (image: Shader),
+///     (image: Texture("sprites/level.png"), scroll: (0.3, 0.1), auto_scroll: (8.0, 0.0)),
+/// ]
+/// ```
+///
+/// Levels without the field get the background shader.
+///
+/// In debug builds a .wgsl shader that is dropped onto the window replaces the
+/// background shader until the layers are respawned. It gets the same bindings as
+/// `shaders/background.wgsl`.
 impl Plugin for BackgroundPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(Material2dPlugin::<YourShader2D>::default())
-            .add_plugins(ShadplayShaderLibrary)
-            .add_systems(OnEnter(GameState::Playing), setup_background);
+            .add_plugins(Material2dPlugin::<ParallaxMaterial>::default())
+            .add_plugins(Material2dPlugin::<BackgroundPreview>::default())
+            .init_resource::<ParallaxLayers>()
+            .add_systems(
+                Update,
+                spawn_parallax_layers
+                    .run_if(resource_changed::<CurrentRoom>())
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PostUpdate,
+                (scroll_parallax_layers, update_background_shaders)
+                    .chain()
+                    .after(CameraFollowSet)
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::Playing), cleanup);
+
+        #[cfg(debug_assertions)]
+        app.add_systems(
+            Update,
+            preview_dropped_shader.run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// What a parallax layer draws
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum ParallaxImage {
+    /// The background shader
+    Shader,
+    /// A texture that repeats in every direction
+    Texture(String),
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ParallaxLayerConfig {
+    pub image: ParallaxImage,
+    /// How far the layer moves with the camera: 0 stays on screen, 1 moves with the
+    /// level
+    #[serde(default)]
+    pub scroll: (f32, f32),
+    /// How fast the layer moves by itself, in pixels per second
+    #[serde(default)]
+    pub auto_scroll: (f32, f32),
+}
+
+/// The layers that are spawned, from back to front
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct ParallaxLayers(pub Vec<ParallaxLayerConfig>);
+
+impl Default for ParallaxLayers {
+    fn default() -> Self {
+        ParallaxLayers(vec![ParallaxLayerConfig {
+            image: ParallaxImage::Shader,
+            scroll: (0.0, 0.0),
+            auto_scroll: (0.0, 0.0),
+        }])
+    }
+}
+
+impl ParallaxLayers {
+    fn from_level(level: &Level) -> Self {
+        let Some(parallax) = fields::level_string_field(level, "parallax") else {
+            return ParallaxLayers::default();
+        };
+
+        match ron::from_str(&parallax) {
+            Ok(layers) => ParallaxLayers(layers),
+            Err(error) => {
+                warn!("invalid parallax layers in {}: {}", level.identifier, error);
+                ParallaxLayers::default()
+            }
+        }
     }
 }
 
-pub fn setup_background(
+/// A quad that covers the view of a camera and scrolls its image. Each camera gets its
+/// own layers, on its `BackgroundLayer`, so the split screen halves scroll on their own.
+#[derive(Component, Clone, Debug)]
+pub struct ParallaxLayer {
+    pub camera: Entity,
+    pub scroll: Vec2,
+    pub auto_scroll: Vec2,
+}
+
+/// Respawns the parallax layers when the player enters a room with different ones
+fn spawn_parallax_layers(
     mut commands: Commands,
+    current_room: Res<CurrentRoom>,
+    played_ldtk: Res<PlayedLdtk>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut your_shader: ResMut<Assets<YourShader2D>>,
-    windows: Query<&Window>,
+    mut shaders: ResMut<Assets<YourShader2D>>,
+    mut materials: ResMut<Assets<ParallaxMaterial>>,
+    mut parallax_layers: ResMut<ParallaxLayers>,
+    spawned: Query<Entity, With<ParallaxLayer>>,
+    cameras: Query<(Entity, &BackgroundLayer)>,
 ) {
-    let win = windows
-        .get_single()
-        .expect("Should be impossible to NOT get a window");
-
-    // Quad
-    commands.spawn((
-        bevy::sprite::MaterialMesh2dBundle {
-            mesh: meshes
-                .add(shape::Quad::new(Vec2::new(win.width(), win.height())).into())
-                .into(),
-            material: your_shader.add(YourShader2D {
-                mouse_pos: MousePos {
-                    x: 100.0f32,
-                    y: 128.0f32,
+    let layers = current_room
+        .iid
+        .as_ref()
+        .zip(ldtk_assets.get(&played_ldtk.0))
+        .and_then(|(iid, ldtk)| ldtk.iter_levels().find(|level| &level.iid == iid))
+        .map(ParallaxLayers::from_level)
+        .unwrap_or_default();
+
+    if !spawned.is_empty() && layers == *parallax_layers {
+        return;
+    }
+
+    for entity in spawned.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // scaled to the camera's view by `scroll_parallax_layers`
+    let mesh: bevy::sprite::Mesh2dHandle = meshes.add(shape::Quad::new(Vec2::ONE).into()).into();
+
+    for ((index, config), (camera, background_layer)) in layers
+        .0
+        .iter()
+        .enumerate()
+        .flat_map(|config| cameras.iter().map(move |camera| (config, camera)))
+    {
+        let layer = ParallaxLayer {
+            camera,
+            scroll: Vec2::from(config.scroll),
+            auto_scroll: Vec2::from(config.auto_scroll),
+        };
+        let transform = Transform::from_translation(Vec3::new(
+            0.,
+            0.,
+            BACKGROUND_Z + index as f32 * LAYER_Z_STEP,
+        ));
+
+        match &config.image {
+            ParallaxImage::Shader => commands.spawn((
+                bevy::sprite::MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: shaders.add(YourShader2D {
+                        layer: ParallaxUniform::default(),
+                        background: BackgroundUniform::default(),
+                    }),
+                    transform,
+                    ..default()
+                },
+                layer,
+                RenderLayers::layer(background_layer.0),
+                Name::new("parallax shader"),
+            )),
+            ParallaxImage::Texture(path) => commands.spawn((
+                bevy::sprite::MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: materials.add(ParallaxMaterial {
+                        layer: ParallaxUniform::default(),
+                        texture: asset_server.load(path),
+                    }),
+                    transform,
+                    ..default()
                 },
-            }),
-            transform: Transform::from_translation(Vec3::new(0., 0., -10.)),
-            // .with_rotation(Quat::from_rotation_x(180.0)), //FIX ME to avoid the rotate2D call in all shaders..
-            ..default()
-        },
-        // BillBoardQuad,
-    ));
+                layer,
+                RenderLayers::layer(background_layer.0),
+                Name::new("parallax texture"),
+            )),
+        };
+    }
+
+    *parallax_layers = layers;
+}
+
+/// Keeps the layers in front of their camera and moves their images by their scroll
+/// factor
+fn scroll_parallax_layers(
+    time: Res<Time>,
+    cameras: Query<(&Transform, &OrthographicProjection), Without<ParallaxLayer>>,
+    mut layers: Query<(
+        &ParallaxLayer,
+        &mut Transform,
+        Option<&Handle<YourShader2D>>,
+        Option<&Handle<BackgroundPreview>>,
+        Option<&Handle<ParallaxMaterial>>,
+    )>,
+    mut shaders: ResMut<Assets<YourShader2D>>,
+    mut previews: ResMut<Assets<BackgroundPreview>>,
+    mut materials: ResMut<Assets<ParallaxMaterial>>,
+) {
+    for (layer, mut transform, shader, preview, material) in layers.iter_mut() {
+        let Ok((camera, projection)) = cameras.get(layer.camera) else {
+            continue;
+        };
+
+        let camera_position = camera.translation.truncate();
+        let size = pixel_perfect::drawn_size(projection);
+
+        transform.translation.x = camera_position.x;
+        transform.translation.y = camera_position.y;
+        transform.scale = size.extend(1.0);
+
+        let uniform = ParallaxUniform {
+            offset: camera_position * layer.scroll + layer.auto_scroll * time.elapsed_seconds(),
+            size,
+        };
+
+        if let Some(shader) = shader.and_then(|shader| shaders.get_mut(shader)) {
+            shader.layer = uniform.clone();
+        }
+        if let Some(preview) = preview.and_then(|preview| previews.get_mut(preview)) {
+            preview.layer = uniform.clone();
+        }
+        if let Some(material) = material.and_then(|material| materials.get_mut(material)) {
+            material.layer = uniform;
+        }
+    }
+}
+
+/// Passes the state of the game to the background shaders, so they can animate and
+/// react to it
+fn update_background_shaders(
+    time: Res<Time>,
+    camera: Query<&Transform, With<MainCamera>>,
+    players: Query<&Transform, With<Player>>,
+    enemies: Query<&Transform, With<Enemy>>,
+    windows: Query<&Window>,
+    mut shaders: ResMut<Assets<YourShader2D>>,
+    mut previews: ResMut<Assets<BackgroundPreview>>,
+) {
+    let (Ok(camera), Ok(win)) = (camera.get_single(), windows.get_single()) else {
+        return;
+    };
+
+    let camera = camera.translation.truncate();
+    // in co-op the shaders see the middle of the players. without a player, e.g. while
+    // the level reloads, the camera stands in for it
+    let player = if players.is_empty() {
+        camera
+    } else {
+        players
+            .iter()
+            .map(|player| player.translation.truncate())
+            .sum::<Vec2>()
+            / players.iter().count() as f32
+    };
+
+    let background = BackgroundUniform {
+        time: time.elapsed_seconds(),
+        danger: enemy::danger_level(
+            player,
+            enemies.iter().map(|enemy| enemy.translation.truncate()),
+        ),
+        resolution: Vec2::new(win.width(), win.height()),
+        camera,
+        player,
+    };
+
+    for (_, shader) in shaders.iter_mut() {
+        shader.background = background.clone();
+    }
+    for (_, preview) in previews.iter_mut() {
+        preview.background = background.clone();
+    }
+}
+
+/// Draws the shader layers with the dropped shader instead of the background shader
+#[cfg(debug_assertions)]
+fn preview_dropped_shader(
+    mut commands: Commands,
+    mut dropped_shaders: EventReader<DragNDropShader>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut previews: ResMut<Assets<BackgroundPreview>>,
+    layers: Query<Entity, (With<ParallaxLayer>, With<Handle<YourShader2D>>)>,
+) {
+    let Some(dropped) = dropped_shaders.iter().last() else {
+        return;
+    };
+
+    let source = match std::fs::read_to_string(&dropped.path) {
+        Ok(source) => source,
+        Err(error) => {
+            warn!("could not read the shader {:?}: {}", dropped.path, error);
+            return;
+        }
+    };
+
+    info!("previewing the shader {:?}", dropped.path);
+    shaders.set_untracked(
+        PREVIEW_SHADER_HANDLE,
+        Shader::from_wgsl(source, dropped.path.to_string_lossy().into_owned()),
+    );
+
+    for entity in layers.iter() {
+        commands
+            .entity(entity)
+            .remove::<Handle<YourShader2D>>()
+            .insert(previews.add(BackgroundPreview {
+                layer: ParallaxUniform::default(),
+                background: BackgroundUniform::default(),
+            }));
+    }
+}
+
+fn cleanup(mut commands: Commands, layers: Query<Entity, With<ParallaxLayer>>) {
+    for entity in layers.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Where a parallax layer's image is: the offset of the view's center and the size of
+/// the view, in pixels
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct ParallaxUniform {
+    pub offset: Vec2,
+    pub size: Vec2,
+}
+
+/// The state of the game, in the world's pixels and seconds
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct BackgroundUniform {
+    pub time: f32,
+    /// See `enemy::danger_level`
+    pub danger: f32,
+    /// The size of the window
+    pub resolution: Vec2,
+    pub camera: Vec2,
+    pub player: Vec2,
 }
 
 #[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
 #[uuid = "f528511f-dcf2-4b0b-9522-a9df3a1a795b"]
 pub struct YourShader2D {
     #[uniform(0)]
-    pub(crate) mouse_pos: MousePos,
+    pub(crate) layer: ParallaxUniform,
+    #[uniform(1)]
+    pub(crate) background: BackgroundUniform,
     // #[texture(1, dimension = "2d")]
     // #[sampler(2)]
     // pub img: Handle<Image>,
 }
 
-// pub struct YourShader2D {
-//     #[uniform(0)]
-//     pub(crate) mouse_pos: MousePos,
+impl Material2d for YourShader2D {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/background.wgsl".into()
+    }
+}
 
-//     #[texture(1, dimension = "2d")]
-//     #[sampler(2)]
-//     pub img: Handle<Image>,
-// }
+/// The uniforms of `YourShader2D` for a shader that was dropped onto the window
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "3e9b7d21-8c4a-4f5e-b6d0-1a2c3e4f5a67"]
+pub struct BackgroundPreview {
+    #[uniform(0)]
+    pub(crate) layer: ParallaxUniform,
+    #[uniform(1)]
+    pub(crate) background: BackgroundUniform,
+}
 
-#[derive(ShaderType, Debug, Clone)]
-pub struct MousePos {
-    pub x: f32,
-    pub y: f32,
+impl Material2d for BackgroundPreview {
+    fn fragment_shader() -> ShaderRef {
+        PREVIEW_SHADER_HANDLE.typed().into()
+    }
 }
 
-impl Material2d for YourShader2D {
+/// A repeating texture
+#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "7c2a9e41-5b3d-4f86-a1e0-3d8b6c94f215"]
+pub struct ParallaxMaterial {
+    #[uniform(0)]
+    pub layer: ParallaxUniform,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+}
+
+impl Material2d for ParallaxMaterial {
     fn fragment_shader() -> ShaderRef {
-        "shaders/background.wgsl".into()
+        "shaders/parallax.wgsl".into()
     }
 }
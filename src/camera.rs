@@ -1,9 +1,55 @@
-use bevy::prelude::*;
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{camera::Viewport, view::RenderLayers},
+};
 
-use crate::{player::Player, GameState};
+use crate::{
+    contacts::GroundContacts,
+    level::{
+        rooms::{
+            current_room_bounds, level_bounds, CurrentRoom, RoomCamera, RoomChanged,
+            RoomTransitionSettings,
+        },
+        triggers::{TriggerAction, TriggerEntered},
+        PlayedLdtk,
+    },
+    player::Player,
+    timestep::interpolate_transforms,
+    GameState,
+};
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::LinearVelocity;
+use effects::{CameraEffects, CameraShake, HitStop, HitStopTimer, ZoomPunch};
+use pixel_perfect::PixelSnap;
 
-/// The amount to zoom the scale the camera projection (lower = zoom in)
-pub const CAMERA_ZOOM: f32 = 0.25;
+pub mod effects;
+pub mod pixel_perfect;
+
+/// The range of scales `CameraChange` trigger zones can set (higher = zoom out).
+/// Zooming in isn't allowed, the world's pixels would be drawn unevenly, and at 4 the
+/// 400x225 view already shows a 1600x900 area. Matches the min and max of the `zoom`
+/// field in ldtk, so `validate-levels` reports zones outside of it.
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 4.0;
+
+/// Below this horizontal speed the camera looks ahead where the player faces, in pixels
+/// per second
+const LOOK_AHEAD_MIN_SPEED: f32 = 20.0;
+
+/// How close the players may get to the edges of the shared view before the screen is
+/// split, in world pixels
+const SPLIT_MARGIN: f32 = 32.0;
+
+/// How close to the edges of the shared view the players have to be again before the
+/// screen is joined. Larger than `SPLIT_MARGIN`, so it doesn't flicker between the two.
+const JOIN_MARGIN: f32 = 64.0;
+
+/// The render layers of the parallax backgrounds of the main and the split camera
+const MAIN_BACKGROUND_LAYER: u8 = 2;
+const SPLIT_BACKGROUND_LAYER: u8 = 3;
 
 pub struct CameraPlugin;
 
@@ -14,27 +60,135 @@ impl Plugin for CameraPlugin {
             (spawn_camera_and_next_state).chain(),
         )
         .add_systems(
-            OnExit(GameState::Initializing),
-            (scale_camera_projection).chain(),
+            Update,
+            zoom_from_triggers.run_if(in_state(GameState::Playing)),
+        )
+        .register_type::<CameraRig>()
+        .init_resource::<CameraRig>()
+        .init_resource::<SplitScreen>()
+        .init_resource::<HitStopTimer>()
+        .add_event::<CameraShake>()
+        .add_event::<ZoomPunch>()
+        .add_event::<HitStop>()
+        .add_systems(Update, effects::hit_stop)
+        .add_systems(
+            OnExit(GameState::Playing),
+            (
+                reset_focus,
+                (reset_split_screen, split_viewports).chain(),
+                effects::reset_camera_effects,
+            ),
         )
-        .add_systems(Update, follow_player.run_if(in_state(GameState::Playing)));
+        .add_systems(
+            PostUpdate,
+            (
+                pixel_perfect::unsnap_camera,
+                effects::remove_camera_effects,
+                update_split_screen,
+                split_viewports.run_if(resource_changed::<SplitScreen>()),
+                snap_to_new_room,
+                follow_player,
+                effects::apply_camera_effects,
+                pixel_perfect::snap_camera,
+            )
+                .chain()
+                .in_set(CameraFollowSet)
+                .after(interpolate_transforms)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(PostUpdate, pixel_perfect::upscale.after(CameraFollowSet));
     }
 }
 
 #[derive(Component)]
-struct MainCamera;
+pub struct MainCamera;
 
-fn spawn_camera_and_next_state(mut commands: Commands, mut state: ResMut<NextState<GameState>>) {
-    commands.spawn(Camera2dBundle::default()).insert(MainCamera);
-    state.set(GameState::Loading);
+/// Shows the second player on the right half of the screen, while the players are too
+/// far apart to share the view of the main camera. The main camera keeps the left half.
+#[derive(Component)]
+pub struct SplitCamera;
+
+/// The render layer only this camera draws its parallax background on
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BackgroundLayer(pub u8);
+
+/// Whether the screen is split between the players
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SplitScreen {
+    pub active: bool,
 }
 
-fn scale_camera_projection(mut q: Query<&mut OrthographicProjection, With<MainCamera>>) {
-    let mut projection = q.single_mut();
+/// Moves the camera after the player, keeping its view inside the current room, plays
+/// the camera effects on top and snaps it to whole texels
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CameraFollowSet;
 
-    // scale camera
-    projection.scale *= CAMERA_ZOOM;
-    projection.scale = projection.scale.clamp(0.25, 5.0);
+/// Spawns the main and the split camera, drawing into the low resolution image, see
+/// `pixel_perfect`
+fn spawn_camera_and_next_state(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let target = pixel_perfect::low_res_target(&mut commands, &mut images);
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: target.clone(),
+                ..default()
+            },
+            ..default()
+        },
+        // the ui is drawn by the upscale camera, at the window's resolution
+        UiCameraConfig { show_ui: false },
+        RenderLayers::from_layers(&[0, MAIN_BACKGROUND_LAYER]),
+        BackgroundLayer(MAIN_BACKGROUND_LAYER),
+        MainCamera,
+        CameraFocus::default(),
+        CameraEffects::default(),
+        PixelSnap::default(),
+    ));
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target,
+                order: 1,
+                is_active: false,
+                ..default()
+            },
+            // clearing would clear the main camera's half of the image as well
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        UiCameraConfig { show_ui: false },
+        RenderLayers::from_layers(&[0, SPLIT_BACKGROUND_LAYER]),
+        BackgroundLayer(SPLIT_BACKGROUND_LAYER),
+        SplitCamera,
+        CameraFocus::default(),
+        CameraEffects::default(),
+        PixelSnap::default(),
+        Name::new("split camera"),
+    ));
+
+    state.set(GameState::Loading);
+}
+
+/// Applies the zoom of `CameraChange` trigger zones, relative to the default zoom
+fn zoom_from_triggers(
+    mut triggers: EventReader<TriggerEntered>,
+    mut q: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    for event in triggers.iter() {
+        if let Some(TriggerAction::CameraChange { zoom }) = event.action {
+            let mut projection = q.single_mut();
+            projection.scale = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
 }
 
 // fn recenter_camera(mut transform_q: Query<&mut Transform, With<MainCamera>>) {
@@ -46,18 +200,387 @@ fn scale_camera_projection(mut q: Query<&mut OrthographicProjection, With<MainCa
 //     transform.translation = Vec3::new(scaled_camera_transform.x, scaled_camera_transform.y, 0.0);
 // }
 
+/// Where the camera is looking at `target` from, so that its view stays inside
+/// `bounds`. A room that's smaller than the view is centered in it instead.
+fn clamp_to_bounds(target: Vec2, view: Vec2, bounds: Rect) -> Vec2 {
+    let clamp_axis = |target: f32, view: f32, min: f32, max: f32| {
+        if max - min <= view {
+            (min + max) / 2.0
+        } else {
+            target.clamp(min + view / 2.0, max - view / 2.0)
+        }
+    };
+
+    Vec2::new(
+        clamp_axis(target.x, view.x, bounds.min.x, bounds.max.x),
+        clamp_axis(target.y, view.y, bounds.min.y, bounds.max.y),
+    )
+}
+
+/// The view of the camera inside the bounds of the current room, once it's known
+#[derive(SystemParam)]
+struct RoomBounds<'w> {
+    played_ldtk: Res<'w, PlayedLdtk>,
+    ldtk_assets: Res<'w, Assets<LdtkAsset>>,
+    current_room: Res<'w, CurrentRoom>,
+}
+
+impl RoomBounds<'_> {
+    /// Keeps the view inside the room at `room_at`, if given and there is one, or else
+    /// inside the current room
+    fn clamp(&self, target: Vec2, view: Vec2, room_at: Option<Vec2>) -> Vec2 {
+        let bounds = self.ldtk_assets.get(&self.played_ldtk.0).and_then(|ldtk| {
+            room_at
+                .and_then(|position| {
+                    ldtk.iter_levels()
+                        .map(level_bounds)
+                        .find(|bounds| bounds.contains(position))
+                })
+                .or_else(|| current_room_bounds(ldtk, &self.current_room))
+        });
+
+        let Some(bounds) = bounds else {
+            return target;
+        };
+
+        clamp_to_bounds(target, view, bounds)
+    }
+}
+
+/// The part of the world a camera shows, which is halved in width while the screen is
+/// split
+fn view_size(projection: &OrthographicProjection, split_screen: &SplitScreen) -> Vec2 {
+    let view = pixel_perfect::visible_size(projection);
+
+    if split_screen.active {
+        view * Vec2::new(0.5, 1.0)
+    } else {
+        view
+    }
+}
+
+/// What the camera follows: the center of the dead zone, at the height of the platform
+/// the player last landed on, and how far the camera looks ahead of it
+#[derive(Component, Clone, Debug, Default)]
+pub struct CameraFocus {
+    /// The center of the dead zone, `None` until the player is found
+    pub position: Option<Vec2>,
+    pub look_ahead: f32,
+}
+
+impl CameraFocus {
+    /// Where the camera wants to be, before it's kept inside the room
+    fn target(&self) -> Option<Vec2> {
+        self.position
+            .map(|position| position + Vec2::X * self.look_ahead)
+    }
+
+    fn follow(&mut self, rig: &CameraRig, player: &PlayerMotion, delta_seconds: f32) {
+        let position = self.position.get_or_insert(player.position);
+        let half_dead_zone = rig.dead_zone / 2.0;
+
+        position.x = position.x.clamp(
+            player.position.x - half_dead_zone.x,
+            player.position.x + half_dead_zone.x,
+        );
+
+        // the height only changes on landing, unless the player leaves the dead zone
+        if player.grounded {
+            position.y = player.position.y;
+        } else {
+            position.y = position.y.clamp(
+                player.position.y - half_dead_zone.y,
+                player.position.y + half_dead_zone.y,
+            );
+        }
+
+        let direction = if player.velocity.x.abs() > LOOK_AHEAD_MIN_SPEED {
+            player.velocity.x.signum()
+        } else if player.facing_left {
+            -1.0
+        } else {
+            1.0
+        };
+        self.look_ahead += (direction * rig.look_ahead - self.look_ahead)
+            * blend(rig.look_ahead_sharpness, delta_seconds);
+    }
+}
+
+/// How the camera follows the player
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+pub struct CameraRig {
+    /// The size of the area around the focus the player moves in without moving the
+    /// camera, in pixels
+    pub dead_zone: Vec2,
+    /// How far ahead of the player the camera looks, in pixels
+    pub look_ahead: f32,
+    /// How fast the look ahead turns around, see `sharpness`
+    pub look_ahead_sharpness: f32,
+    /// How much of the way to its target the camera moves in a 30th of a second, on each
+    /// axis
+    pub sharpness: Vec2,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            dead_zone: Vec2::new(32.0, 48.0),
+            look_ahead: 24.0,
+            look_ahead_sharpness: 0.05,
+            sharpness: Vec2::new(0.1, 0.06),
+        }
+    }
+}
+
+/// How much of the way to move in `delta_seconds`, for a sharpness per 30th of a second
+fn blend(sharpness: f32, delta_seconds: f32) -> f32 {
+    1.0 - (1.0_f32 - sharpness).powf(delta_seconds * 30.0)
+}
+
+/// What the camera needs to know about the player
+struct PlayerMotion {
+    position: Vec2,
+    velocity: Vec2,
+    grounded: bool,
+    facing_left: bool,
+}
+
+type PlayerMotionQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Player,
+        &'static Transform,
+        Option<&'static LinearVelocity>,
+        Option<&'static GroundContacts>,
+        Option<&'static TextureAtlasSprite>,
+    ),
+>;
+
+/// The motion of each player, with their index
+fn player_motions(players: &PlayerMotionQuery) -> Vec<(usize, PlayerMotion)> {
+    players
+        .iter()
+        .map(|(player, transform, velocity, ground_contacts, sprite)| {
+            let motion = PlayerMotion {
+                position: transform.translation.truncate(),
+                velocity: velocity.map_or(Vec2::ZERO, |velocity| velocity.0),
+                grounded: ground_contacts.map_or(false, GroundContacts::is_grounded),
+                facing_left: sprite.map_or(false, |sprite| sprite.flip_x),
+            };
+            (player.index, motion)
+        })
+        .collect()
+}
+
+/// What a camera follows: its own player while the screen is split, or else the middle
+/// of the players, which only the main camera follows
+fn camera_subject(
+    players: &PlayerMotionQuery,
+    split_screen: &SplitScreen,
+    split_camera: bool,
+) -> Option<PlayerMotion> {
+    let motions = player_motions(players);
+
+    if split_screen.active {
+        let index = usize::from(split_camera);
+        return motions
+            .into_iter()
+            .find(|(player, _)| *player == index)
+            .map(|(_, motion)| motion);
+    }
+
+    if split_camera || motions.is_empty() {
+        return None;
+    }
+
+    let count = motions.len() as f32;
+
+    Some(PlayerMotion {
+        position: motions
+            .iter()
+            .map(|(_, motion)| motion.position)
+            .sum::<Vec2>()
+            / count,
+        velocity: motions
+            .iter()
+            .map(|(_, motion)| motion.velocity)
+            .sum::<Vec2>()
+            / count,
+        grounded: motions.iter().all(|(_, motion)| motion.grounded),
+        facing_left: motions.iter().all(|(_, motion)| motion.facing_left),
+    })
+}
+
+/// Splits the screen once the players don't fit into the shared view anymore, and joins
+/// it again once they are close enough
+fn update_split_screen(
+    mut split_screen: ResMut<SplitScreen>,
+    camera: Query<&OrthographicProjection, With<MainCamera>>,
+    players: Query<&Transform, With<Player>>,
+) {
+    let Ok(projection) = camera.get_single() else {
+        return;
+    };
+
+    let margin = if split_screen.active {
+        JOIN_MARGIN
+    } else {
+        SPLIT_MARGIN
+    };
+    let room = pixel_perfect::visible_size(projection) - Vec2::splat(2.0 * margin);
+
+    let (min, max) = players
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), position| {
+            (min.min(position), max.max(position))
+        });
+    let active = players.iter().count() > 1 && (max - min).cmpgt(room).any();
+
+    if split_screen.active != active {
+        split_screen.active = active;
+    }
+}
+
+/// Gives each camera its half of the image while the screen is split, and the main
+/// camera all of it otherwise
+fn split_viewports(
+    split_screen: Res<SplitScreen>,
+    mut main_camera: Query<&mut Camera, (With<MainCamera>, Without<SplitCamera>)>,
+    mut split_camera: Query<(&mut Camera, &mut CameraFocus), With<SplitCamera>>,
+) {
+    let (Ok(mut main_camera), Ok((mut split_camera, mut split_focus))) =
+        (main_camera.get_single_mut(), split_camera.get_single_mut())
+    else {
+        return;
+    };
+
+    let size = pixel_perfect::target_size();
+    let left = size.x / 2;
+
+    if split_screen.active {
+        main_camera.viewport = Some(Viewport {
+            physical_position: UVec2::ZERO,
+            physical_size: UVec2::new(left, size.y),
+            ..default()
+        });
+        split_camera.viewport = Some(Viewport {
+            physical_position: UVec2::new(left, 0),
+            physical_size: UVec2::new(size.x - left, size.y),
+            ..default()
+        });
+    } else {
+        main_camera.viewport = None;
+        // the split camera jumps to the player the next time the screen is split
+        *split_focus = CameraFocus::default();
+    }
+
+    split_camera.is_active = split_screen.active;
+}
+
+fn reset_split_screen(mut split_screen: ResMut<SplitScreen>) {
+    *split_screen = SplitScreen::default();
+}
+
+/// Moves the main camera straight to the player when entering a new room, if the room
+/// transitions are set to snap
+fn snap_to_new_room(
+    settings: Res<RoomTransitionSettings>,
+    split_screen: Res<SplitScreen>,
+    mut room_changed: EventReader<RoomChanged>,
+    mut camera: Query<
+        (&mut Transform, &mut CameraFocus, &OrthographicProjection),
+        (With<MainCamera>, Without<Player>),
+    >,
+    players: PlayerMotionQuery,
+    bounds: RoomBounds,
+) {
+    if room_changed.iter().last().is_none() || settings.camera != RoomCamera::Snap {
+        return;
+    }
+
+    let (Ok((mut camera_transform, mut focus, projection)), Some(player)) = (
+        camera.get_single_mut(),
+        camera_subject(&players, &split_screen, false),
+    ) else {
+        return;
+    };
+
+    focus.position = Some(player.position);
+    let target = bounds.clamp(
+        focus.target().unwrap_or(player.position),
+        view_size(projection, &split_screen),
+        None,
+    );
+
+    camera_transform.translation.x = target.x;
+    camera_transform.translation.y = target.y;
+}
+
+/// Moves the cameras toward their focus, smoothed on each axis. Entering a room or
+/// zooming moves them toward the new bounds the same way. The split camera zooms along
+/// with the main camera and keeps its view inside the room of the second player.
 fn follow_player(
-    mut camera: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
-    player: Query<&Transform, With<Player>>,
+    rig: Res<CameraRig>,
+    split_screen: Res<SplitScreen>,
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &mut CameraFocus,
+            &mut OrthographicProjection,
+            Option<&SplitCamera>,
+        ),
+        (Or<(With<MainCamera>, With<SplitCamera>)>, Without<Player>),
+    >,
+    players: PlayerMotionQuery,
+    bounds: RoomBounds,
     time: Res<Time>,
 ) {
-    let follow_sharpness = 0.1;
-    let blend = 1.0 - (1.0_f32 - follow_sharpness).powf(time.delta_seconds() * 30.0);
+    let zoom = cameras
+        .iter()
+        .find(|(.., split_camera)| split_camera.is_none())
+        .map(|(_, _, projection, _)| projection.scale);
+
+    for (mut camera_transform, mut focus, mut projection, split_camera) in cameras.iter_mut() {
+        let Some(player) = camera_subject(&players, &split_screen, split_camera.is_some()) else {
+            continue;
+        };
+
+        if let Some(zoom) = zoom.filter(|zoom| *zoom != projection.scale) {
+            projection.scale = zoom;
+        }
 
-    let mut camera_transform = camera.single_mut();
-    let player_transform = *player.single();
+        // the split camera starts right at its player, the main camera pans over
+        let jump = split_camera.is_some() && focus.position.is_none();
 
-    camera_transform.translation = camera_transform
-        .translation
-        .lerp(player_transform.translation, blend);
+        focus.follow(&rig, &player, time.delta_seconds());
+        let target = bounds.clamp(
+            focus.target().unwrap_or(player.position),
+            view_size(&projection, &split_screen),
+            split_camera.map(|_| player.position),
+        );
+
+        let position = camera_transform.translation.truncate();
+        let smoothing = if jump {
+            Vec2::ONE
+        } else {
+            Vec2::new(
+                blend(rig.sharpness.x, time.delta_seconds()),
+                blend(rig.sharpness.y, time.delta_seconds()),
+            )
+        };
+        let position = position + (target - position) * smoothing;
+
+        camera_transform.translation.x = position.x;
+        camera_transform.translation.y = position.y;
+    }
+}
+
+/// The player starts somewhere else in the next level
+fn reset_focus(mut focus: Query<&mut CameraFocus>) {
+    for mut focus in focus.iter_mut() {
+        *focus = CameraFocus::default();
+    }
 }
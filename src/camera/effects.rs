@@ -0,0 +1,191 @@
+//! Camera feedback that any system can trigger by sending an event: screen shake,
+//! zoom punches and hit-stop. The shake and zoom are applied on top of where the
+//! camera follows the player and taken off again before it follows, so they never
+//! move the follow position. Both halves of a split screen play the same effects. With
+//! `reduce_motion` in the save data they are all off.
+
+use super::{MainCamera, SplitCamera};
+use crate::level::campaign::SaveData;
+use bevy::prelude::*;
+
+/// How far the camera shakes at full trauma, in pixels of the low resolution image
+const MAX_SHAKE_OFFSET: f32 = 12.0;
+
+/// How much trauma wears off per second
+const TRAUMA_DECAY: f32 = 1.5;
+
+/// How fast the camera shakes, in radians per second of the noise waves
+const SHAKE_FREQUENCY: f32 = 45.0;
+
+/// Shakes the camera. Trauma adds up to 1, the shake grows with its square.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+/// Zooms the camera out by `amount` of its scale and back in over `seconds`. Punching
+/// outward keeps the scale at or above the default zoom, so texels stay whole.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ZoomPunch {
+    pub amount: f32,
+    pub seconds: f32,
+}
+
+/// Slows the game down to `time_scale` for `seconds` of real time
+#[derive(Event, Clone, Copy, Debug)]
+pub struct HitStop {
+    pub seconds: f32,
+    pub time_scale: f32,
+}
+
+/// The effects that are playing, and what was applied to the camera for this frame
+#[derive(Component, Clone, Debug, Default)]
+pub struct CameraEffects {
+    trauma: f32,
+    zoom: Option<(f32, Timer)>,
+    applied_offset: Vec2,
+    /// The scale before and after the zoom
+    applied_scale: Option<(f32, f32)>,
+}
+
+impl CameraEffects {
+    fn take_off(&mut self, transform: &mut Transform, projection: &mut OrthographicProjection) {
+        transform.translation -= self.applied_offset.extend(0.0);
+        self.applied_offset = Vec2::ZERO;
+
+        // a zoom trigger may have set a new scale since, that one stays
+        if let Some((before, after)) = self.applied_scale.take() {
+            if projection.scale == after {
+                projection.scale = before;
+            }
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default)]
+pub struct HitStopTimer(Option<Timer>);
+
+/// Takes the effects of the last frame off the cameras, before they follow the players
+pub fn remove_camera_effects(
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut CameraEffects,
+        ),
+        Or<(With<MainCamera>, With<SplitCamera>)>,
+    >,
+) {
+    for (mut transform, mut projection, mut effects) in cameras.iter_mut() {
+        effects.take_off(&mut transform, &mut projection);
+    }
+}
+
+/// Collects the new effects, wears them off and applies them to the cameras
+pub fn apply_camera_effects(
+    time: Res<Time>,
+    save_data: Res<SaveData>,
+    mut shakes: EventReader<CameraShake>,
+    mut zoom_punches: EventReader<ZoomPunch>,
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut CameraEffects,
+        ),
+        Or<(With<MainCamera>, With<SplitCamera>)>,
+    >,
+) {
+    if save_data.reduce_motion {
+        shakes.clear();
+        zoom_punches.clear();
+        return;
+    }
+
+    let trauma = shakes.iter().map(|shake| shake.trauma).sum::<f32>();
+    let punch = zoom_punches.iter().last().copied();
+
+    // real time, so the shake doesn't freeze during a hit-stop
+    let delta = time.raw_delta();
+    let t = time.raw_elapsed_seconds() * SHAKE_FREQUENCY;
+    let noise = Vec2::new(
+        t.sin() + (t * 2.3 + 1.7).sin() * 0.5,
+        (t * 1.3 + 4.1).sin() + (t * 2.9 + 0.6).sin() * 0.5,
+    ) / 1.5;
+
+    for (mut transform, mut projection, mut effects) in cameras.iter_mut() {
+        effects.trauma = (effects.trauma + trauma).min(1.0);
+        if let Some(punch) = punch {
+            effects.zoom = Some((
+                punch.amount,
+                Timer::from_seconds(punch.seconds, TimerMode::Once),
+            ));
+        }
+
+        effects.trauma = (effects.trauma - TRAUMA_DECAY * delta.as_secs_f32()).max(0.0);
+
+        let shake = effects.trauma * effects.trauma;
+        effects.applied_offset = noise * shake * MAX_SHAKE_OFFSET * projection.scale;
+        transform.translation += effects.applied_offset.extend(0.0);
+
+        if let Some((amount, timer)) = &mut effects.zoom {
+            timer.tick(delta);
+
+            let before = projection.scale;
+            projection.scale *= 1.0 + *amount * timer.percent_left();
+            let after = projection.scale;
+
+            if timer.finished() {
+                effects.zoom = None;
+            }
+            effects.applied_scale = Some((before, after));
+        }
+    }
+}
+
+/// Slows the game down during a hit-stop. Fixed updates follow the scaled time, so the
+/// simulation slows down with it.
+pub fn hit_stop(
+    mut time: ResMut<Time>,
+    save_data: Res<SaveData>,
+    mut hit_stops: EventReader<HitStop>,
+    mut timer: ResMut<HitStopTimer>,
+) {
+    if let Some(hit_stop) = hit_stops.iter().last() {
+        if !save_data.reduce_motion {
+            time.set_relative_speed(hit_stop.time_scale);
+            timer.0 = Some(Timer::from_seconds(hit_stop.seconds, TimerMode::Once));
+        }
+    }
+
+    let Some(stop) = &mut timer.0 else {
+        return;
+    };
+
+    if stop.tick(time.raw_delta()).finished() {
+        time.set_relative_speed(1.0);
+        timer.0 = None;
+    }
+}
+
+/// Stops the effects when the game stops playing
+pub fn reset_camera_effects(
+    mut time: ResMut<Time>,
+    mut timer: ResMut<HitStopTimer>,
+    mut cameras: Query<
+        (
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut CameraEffects,
+        ),
+        Or<(With<MainCamera>, With<SplitCamera>)>,
+    >,
+) {
+    time.set_relative_speed(1.0);
+    timer.0 = None;
+
+    for (mut transform, mut projection, mut effects) in cameras.iter_mut() {
+        effects.take_off(&mut transform, &mut projection);
+        *effects = CameraEffects::default();
+    }
+}
@@ -0,0 +1,221 @@
+//! The game is drawn into a low resolution image, one texel per world pixel at the
+//! default zoom, which a second camera scales up to the window as a sprite. The
+//! `ResolutionMode` in the save data picks how it's scaled.
+//!
+//! The main camera is snapped to whole texels before it draws, so sprites don't
+//! shimmer as it moves. What the snapping took off is made up by moving the upscaled
+//! image by a fraction of a texel, so the camera still moves smoothly. The image has a
+//! margin of texels around the view for that. While the screen is split, both cameras
+//! are snapped but the image isn't moved, as the halves would need different
+//! remainders. The split view moves in whole texels then.
+
+use super::{MainCamera, SplitScreen};
+use crate::level::campaign::SaveData;
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        view::RenderLayers,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// The size of the view in world pixels at the default zoom, `GAME_SIZE` scaled down
+/// four times
+pub const LOW_RES_SIZE: UVec2 = UVec2::new(400, 225);
+
+/// The texels drawn around the view on each side
+const MARGIN: u32 = 1;
+
+/// The layer of the upscaled image, which the main camera doesn't draw
+const UPSCALE_LAYER: u8 = 1;
+
+/// How the low resolution image is scaled up to the window
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResolutionMode {
+    /// The largest whole multiple that fits the window, with black bars around it
+    #[default]
+    PixelPerfect,
+    /// As large as it fits the window, with black bars on two sides
+    Fit,
+    /// Stretched over the whole window
+    Stretch,
+}
+
+impl ResolutionMode {
+    pub fn next(self) -> Self {
+        match self {
+            ResolutionMode::PixelPerfect => ResolutionMode::Fit,
+            ResolutionMode::Fit => ResolutionMode::Stretch,
+            ResolutionMode::Stretch => ResolutionMode::PixelPerfect,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ResolutionMode::PixelPerfect => "Pixel perfect",
+            ResolutionMode::Fit => "Fit",
+            ResolutionMode::Stretch => "Stretch",
+        }
+    }
+}
+
+/// The camera that draws the upscaled image and the UI to the window
+#[derive(Component)]
+pub struct UpscaleCamera;
+
+/// The sprite that shows the image the main camera draws into
+#[derive(Component)]
+pub struct UpscaleSprite;
+
+/// Where the main camera really is, before it was snapped to whole texels
+#[derive(Component, Clone, Debug, Default)]
+pub struct PixelSnap {
+    unsnapped: Option<Vec2>,
+    /// What the snapping took off, in texels
+    remainder: Vec2,
+}
+
+/// The part of the world the camera shows, in world pixels
+pub fn visible_size(projection: &OrthographicProjection) -> Vec2 {
+    LOW_RES_SIZE.as_vec2() * projection.scale
+}
+
+/// The size of the image the cameras draw into, in texels
+pub fn target_size() -> UVec2 {
+    LOW_RES_SIZE + UVec2::splat(2 * MARGIN)
+}
+
+/// The part of the world the camera draws, with the margin, in world pixels
+pub fn drawn_size(projection: &OrthographicProjection) -> Vec2 {
+    target_size().as_vec2() * projection.scale
+}
+
+/// Creates the image the main camera draws into and spawns the camera that shows it
+pub fn low_res_target(commands: &mut Commands, images: &mut Assets<Image>) -> RenderTarget {
+    let size = Extent3d {
+        width: target_size().x,
+        height: target_size().y,
+        depth_or_array_layers: 1,
+    };
+
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("low res target"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    // fills the image with zeros
+    image.resize(size);
+
+    let image = images.add(image);
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                // after the main and the split camera
+                order: 2,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+            },
+            ..default()
+        },
+        RenderLayers::layer(UPSCALE_LAYER),
+        UpscaleCamera,
+        Name::new("upscale camera"),
+    ));
+
+    commands.spawn((
+        SpriteBundle {
+            texture: image.clone(),
+            ..default()
+        },
+        RenderLayers::layer(UPSCALE_LAYER),
+        UpscaleSprite,
+        Name::new("upscaled image"),
+    ));
+
+    RenderTarget::Image(image)
+}
+
+/// Puts the cameras back where they were before they were snapped, so they follow from
+/// there
+pub fn unsnap_camera(mut cameras: Query<(&mut Transform, &mut PixelSnap)>) {
+    for (mut transform, mut snap) in cameras.iter_mut() {
+        if let Some(unsnapped) = snap.unsnapped.take() {
+            transform.translation.x = unsnapped.x;
+            transform.translation.y = unsnapped.y;
+        }
+    }
+}
+
+/// Snaps the cameras to whole texels
+pub fn snap_camera(mut cameras: Query<(&mut Transform, &OrthographicProjection, &mut PixelSnap)>) {
+    for (mut transform, projection, mut snap) in cameras.iter_mut() {
+        let unsnapped = transform.translation.truncate();
+        let snapped = (unsnapped / projection.scale).round() * projection.scale;
+
+        transform.translation.x = snapped.x;
+        transform.translation.y = snapped.y;
+        snap.unsnapped = Some(unsnapped);
+        snap.remainder = (unsnapped - snapped) / projection.scale;
+    }
+}
+
+/// Scales the image up to the window and moves it by what the snapping took off
+pub fn upscale(
+    save_data: Res<SaveData>,
+    split_screen: Res<SplitScreen>,
+    windows: Query<&Window>,
+    camera: Query<&PixelSnap, With<MainCamera>>,
+    mut sprite: Query<&mut Sprite, With<UpscaleSprite>>,
+) {
+    let (Ok(win), Ok(mut sprite)) = (windows.get_single(), sprite.get_single_mut()) else {
+        return;
+    };
+    let remainder = match camera.get_single() {
+        Ok(snap) if !split_screen.active => snap.remainder,
+        _ => Vec2::ZERO,
+    };
+
+    let low_res = LOW_RES_SIZE.as_vec2();
+    let physical = Vec2::new(win.physical_width() as f32, win.physical_height() as f32);
+    let fits = physical / low_res;
+
+    // in physical pixels, so whole multiples stay whole on scaled displays
+    let scale = match save_data.resolution_mode {
+        ResolutionMode::PixelPerfect => Vec2::splat(fits.min_element().floor().max(1.0)),
+        ResolutionMode::Fit => Vec2::splat(fits.min_element()),
+        ResolutionMode::Stretch => fits,
+    };
+
+    // the image's y axis points down
+    let min = Vec2::splat(MARGIN as f32) + Vec2::new(remainder.x, -remainder.y);
+
+    sprite.custom_size = Some(low_res * scale / win.scale_factor() as f32);
+    sprite.rect = Some(Rect::from_corners(min, min + low_res));
+}
+
+/// Where a position in the window is in the image the main camera draws into, to pass
+/// to its `viewport_to_world_2d`
+pub fn window_to_target(position: Vec2, window: &Window, sprite: &Sprite) -> Option<Vec2> {
+    let (size, rect) = (sprite.custom_size?, sprite.rect?);
+    let top_left = (Vec2::new(window.width(), window.height()) - size) / 2.0;
+
+    Some(rect.min + (position - top_left) / size * rect.size())
+}
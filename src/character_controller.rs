@@ -0,0 +1,308 @@
+use crate::{
+    physics::{InitSpriteRigidBody, PhysicsLayers, MAX_SLOPE_NORMAL_Y},
+    timestep::{FixedGameplaySet, SimulationTime},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// How far the controller keeps characters away from surfaces, so that casts never
+/// start inside another collider
+const SKIN_WIDTH: f32 = 0.5;
+
+/// How many times a single move may slide along a surface before it stops
+const MAX_SLIDES: usize = 4;
+
+pub struct CharacterControllerPlugin;
+
+/// This plugin moves characters that use a `KinematicController` with shape casts
+/// (move-and-slide) instead of the physics solver. It runs every fixed tick after all
+/// gameplay systems have set the character velocities and before the physics step.
+impl Plugin for CharacterControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CharacterBody>().add_systems(
+            FixedUpdate,
+            move_kinematic_characters
+                .after(FixedGameplaySet)
+                .before(PhysicsSet::Prepare)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Selects how a character is moved
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum CharacterBody {
+    /// A dynamic rigid body, moved by the physics solver
+    #[default]
+    Dynamic,
+    /// A kinematic rigid body, moved by the `KinematicController`
+    Kinematic,
+}
+
+impl CharacterBody {
+    /// The rigid body `init_sprite_physics` should set up for this character
+    pub fn init_rigid_body(&self) -> InitSpriteRigidBody {
+        match self {
+            CharacterBody::Dynamic => InitSpriteRigidBody::Dynamic,
+            CharacterBody::Kinematic => InitSpriteRigidBody::Kinematic,
+        }
+    }
+}
+
+/// The state and settings of a kinematic character. The character's own `Collider` is
+/// the shape that is cast when moving.
+///
+/// Gameplay systems keep writing the intended velocity into `LinearVelocity`, like they
+/// do for dynamic characters. The controller consumes it every frame: the horizontal
+/// velocity is used as is, and a non-zero vertical velocity (a jump or a lunge) replaces
+/// the vertical velocity the controller has been integrating. Afterwards
+/// `LinearVelocity` is cleared so the physics step doesn't move the body a second time.
+#[derive(Component, Clone, Debug)]
+pub struct KinematicController {
+    /// The highest ledge the character walks up onto without jumping
+    pub step_height: f32,
+    /// How far below the character the ground may drop away and still be followed
+    pub snap_distance: f32,
+    /// The steepest walkable slope, as the minimum y component of the surface normal
+    pub max_slope_normal_y: f32,
+    /// The velocity the character actually moved with during the last frame
+    pub velocity: Vec2,
+    pub grounded: bool,
+    pub ground: Option<Entity>,
+    pub ground_normal: Vec2,
+}
+
+impl Default for KinematicController {
+    fn default() -> Self {
+        KinematicController {
+            step_height: 8.0,
+            snap_distance: 8.0,
+            max_slope_normal_y: MAX_SLOPE_NORMAL_Y,
+            velocity: Vec2::ZERO,
+            grounded: false,
+            ground: None,
+            ground_normal: Vec2::Y,
+        }
+    }
+}
+
+impl KinematicController {
+    fn is_walkable(&self, normal: Vec2) -> bool {
+        normal.y >= self.max_slope_normal_y
+    }
+}
+
+/// Move-and-slide for every kinematic character, followed by ground snapping and a
+/// ground probe that updates `grounded` for the next frame
+pub fn move_kinematic_characters(
+    time: Res<SimulationTime>,
+    gravity: Res<Gravity>,
+    spatial_query: SpatialQuery,
+    mut characters: Query<(
+        Entity,
+        &mut KinematicController,
+        &mut LinearVelocity,
+        &mut Position,
+        &CollisionLayers,
+        &Collider,
+    )>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut controller, mut linear_velocity, mut position, layers, shape) in
+        characters.iter_mut()
+    {
+        // trigger zones and hazards are sensors, the character moves through them
+        let sensors = PhysicsLayers::Trigger.to_bits() | PhysicsLayers::Hazard.to_bits();
+        let filter = SpatialQueryFilter::new()
+            .with_masks_from_bits(layers.masks_bits() & !sensors)
+            .without_entities([entity]);
+        let caster = Caster {
+            spatial_query: &spatial_query,
+            shape,
+            filter: &filter,
+        };
+
+        let mut velocity = Vec2::new(
+            linear_velocity.x,
+            if linear_velocity.y != 0.0 {
+                linear_velocity.y
+            } else {
+                controller.velocity.y
+            },
+        );
+        velocity += gravity.0 * dt;
+
+        let on_ground = controller.grounded && velocity.y <= 0.0;
+        if on_ground {
+            velocity.y = 0.0;
+        }
+
+        let mut motion = velocity * dt;
+
+        // walk along the slope of the ground instead of into or off it
+        if on_ground {
+            let normal = controller.ground_normal;
+            motion = Vec2::new(motion.x, -normal.x * motion.x / normal.y);
+        }
+
+        let (mut new_position, hit_ceiling) =
+            move_and_slide(&caster, &controller, position.0, motion, on_ground);
+
+        // stay on the ground when walking down slopes or over small drops
+        if on_ground {
+            if let Some((hit, normal)) = caster.cast(
+                new_position,
+                Vec2::NEG_Y,
+                controller.snap_distance + SKIN_WIDTH,
+            ) {
+                if controller.is_walkable(normal) {
+                    new_position.y -= (hit.time_of_impact - SKIN_WIDTH).max(0.0);
+                }
+            }
+        }
+
+        // probe for the ground the character ends up on
+        let ground = caster
+            .cast(new_position, Vec2::NEG_Y, SKIN_WIDTH * 2.0)
+            .filter(|(_, normal)| controller.is_walkable(*normal));
+
+        controller.grounded = ground.is_some();
+        controller.ground = ground.as_ref().map(|(hit, _)| hit.entity);
+        controller.ground_normal = ground.map(|(_, normal)| normal).unwrap_or(Vec2::Y);
+
+        if (controller.grounded && velocity.y < 0.0) || (hit_ceiling && velocity.y > 0.0) {
+            velocity.y = 0.0;
+        }
+
+        position.0 = new_position;
+        controller.velocity = velocity;
+        linear_velocity.0 = Vec2::ZERO;
+    }
+}
+
+/// Casts the character shape against the world, ignoring the character itself
+struct Caster<'a, 'w, 's> {
+    spatial_query: &'a SpatialQuery<'w, 's>,
+    shape: &'a Collider,
+    filter: &'a SpatialQueryFilter,
+}
+
+impl Caster<'_, '_, '_> {
+    /// Returns the hit and the normal of the surface that was hit, facing against the
+    /// cast direction
+    fn cast(&self, origin: Vec2, direction: Vec2, distance: f32) -> Option<(ShapeHitData, Vec2)> {
+        self.spatial_query
+            .cast_shape(
+                self.shape,
+                origin,
+                0.0,
+                direction,
+                distance,
+                true,
+                self.filter.clone(),
+            )
+            .map(|hit| {
+                let normal = if hit.normal1.dot(direction) > 0.0 {
+                    -hit.normal1
+                } else {
+                    hit.normal1
+                };
+                (hit, normal)
+            })
+    }
+}
+
+/// Moves the shape as far as possible along `motion`, sliding along whatever it hits.
+/// Returns the new position and whether a ceiling was hit.
+fn move_and_slide(
+    caster: &Caster,
+    controller: &KinematicController,
+    start: Vec2,
+    motion: Vec2,
+    on_ground: bool,
+) -> (Vec2, bool) {
+    let mut position = start;
+    let mut remaining = motion;
+    let mut hit_ceiling = false;
+
+    for _ in 0..MAX_SLIDES {
+        let distance = remaining.length();
+        if distance <= f32::EPSILON {
+            break;
+        }
+
+        let direction = remaining / distance;
+
+        let Some((hit, normal)) = caster.cast(position, direction, distance + SKIN_WIDTH) else {
+            position += remaining;
+            break;
+        };
+
+        let travel = (hit.time_of_impact - SKIN_WIDTH).clamp(0.0, distance);
+        position += direction * travel;
+        remaining = direction * (distance - travel);
+
+        if normal.y < -controller.max_slope_normal_y {
+            hit_ceiling = true;
+        }
+
+        // walk up onto small ledges instead of stopping in front of them
+        if on_ground && normal.y.abs() < controller.max_slope_normal_y && remaining.x != 0.0 {
+            if let Some(stepped) = step_up(caster, controller, position, remaining.x) {
+                position = stepped;
+                break;
+            }
+        }
+
+        // slopes too steep to walk on act like walls when moving into them
+        let slide_normal =
+            if !controller.is_walkable(normal) && normal.y > 0.0 && remaining.y >= 0.0 {
+                Vec2::new(normal.x, 0.0).normalize_or_zero()
+            } else {
+                normal
+            };
+
+        remaining -= slide_normal * remaining.dot(slide_normal);
+    }
+
+    (position, hit_ceiling)
+}
+
+/// Tries to move up by at most `step_height`, forward by `forward`, and back down onto
+/// walkable ground. Returns the position on top of the step if that works.
+fn step_up(
+    caster: &Caster,
+    controller: &KinematicController,
+    position: Vec2,
+    forward: f32,
+) -> Option<Vec2> {
+    let height = caster
+        .cast(position, Vec2::Y, controller.step_height + SKIN_WIDTH)
+        .map(|(hit, _)| hit.time_of_impact - SKIN_WIDTH)
+        .unwrap_or(controller.step_height);
+
+    if height <= 0.0 {
+        return None;
+    }
+
+    let raised = position + Vec2::Y * height;
+    let direction = Vec2::X * forward.signum();
+
+    if caster
+        .cast(raised, direction, forward.abs() + SKIN_WIDTH)
+        .is_some()
+    {
+        return None;
+    }
+
+    let ahead = raised + Vec2::X * forward;
+    let (hit, normal) = caster.cast(ahead, Vec2::NEG_Y, height + SKIN_WIDTH)?;
+
+    if !controller.is_walkable(normal) {
+        return None;
+    }
+
+    Some(ahead - Vec2::Y * (hit.time_of_impact - SKIN_WIDTH).max(0.0))
+}
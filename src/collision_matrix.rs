@@ -0,0 +1,120 @@
+use crate::{config::RonAssetLoader, loading::ConfigAssets, physics::PhysicsLayers, GameState};
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use bevy_xpbd_2d::prelude::*;
+use serde::Deserialize;
+
+pub struct CollisionMatrixPlugin;
+
+/// This plugin provides the `CollisionMatrix` resource, which defines which
+/// `PhysicsLayers` interact. It starts out with the built in matrix and is replaced by
+/// the one in `assets/config/layers.collision.ron` once the assets are loaded.
+impl Plugin for CollisionMatrixPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<CollisionMatrix>()
+            .add_asset_loader(RonAssetLoader::<CollisionMatrix>::new(&["collision.ron"]))
+            .init_resource::<CollisionMatrix>()
+            .add_systems(OnExit(GameState::Loading), apply_collision_matrix);
+    }
+}
+
+/// For every layer, the layers it interacts with. Two layers only collide if each one
+/// lists the other, so the matrix has to be symmetric.
+#[derive(Resource, Deserialize, TypeUuid, TypePath, Clone, Debug)]
+#[uuid = "5b8c3f4e-1a2d-4c6e-9f0b-7d3e2a1c8b64"]
+pub struct CollisionMatrix {
+    pub layers: HashMap<PhysicsLayers, Vec<PhysicsLayers>>,
+}
+
+/// The built in matrix is the asset as it was at compile time, so the two never drift
+/// apart
+impl Default for CollisionMatrix {
+    fn default() -> Self {
+        let matrix: CollisionMatrix =
+            ron::from_str(include_str!("../assets/config/layers.collision.ron"))
+                .expect("the built in collision matrix should parse");
+
+        debug_assert!(
+            matrix.asymmetries().is_empty(),
+            "built in collision matrix is not symmetric: {:?}",
+            matrix.asymmetries()
+        );
+
+        matrix
+    }
+}
+
+impl CollisionMatrix {
+    /// The layers a body in `layer` interacts with
+    pub fn masks(&self, layer: PhysicsLayers) -> Vec<PhysicsLayers> {
+        self.layers.get(&layer).cloned().unwrap_or_default()
+    }
+
+    /// The `CollisionLayers` of a body in `layer`
+    pub fn collision_layers(&self, layer: PhysicsLayers) -> CollisionLayers {
+        CollisionLayers::new([layer], self.masks(layer))
+    }
+
+    /// The pairs of layers where the first one lists the second one, but not the other
+    /// way around
+    pub fn asymmetries(&self) -> Vec<(PhysicsLayers, PhysicsLayers)> {
+        self.layers
+            .iter()
+            .flat_map(|(layer, masks)| masks.iter().map(move |mask| (*layer, *mask)))
+            .filter(|(layer, mask)| !self.masks(*mask).contains(layer))
+            .collect()
+    }
+}
+
+/// Replaces the built in matrix with the loaded one. Debug builds make sure it is
+/// symmetric.
+fn apply_collision_matrix(
+    mut commands: Commands,
+    config: Res<ConfigAssets>,
+    matrices: Res<Assets<CollisionMatrix>>,
+) {
+    let Some(matrix) = matrices.get(&config.collision_matrix) else {
+        warn!("collision matrix not loaded, using the built in one");
+        return;
+    };
+
+    debug_assert!(
+        matrix.asymmetries().is_empty(),
+        "collision matrix is not symmetric: {:?}",
+        matrix.asymmetries()
+    );
+
+    commands.insert_resource(matrix.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_matrix_is_symmetric() {
+        assert_eq!(CollisionMatrix::default().asymmetries(), Vec::new());
+    }
+
+    #[test]
+    fn every_layer_interacts_with_something() {
+        let matrix = CollisionMatrix::default();
+
+        for layer in [
+            PhysicsLayers::Player,
+            PhysicsLayers::Enemy,
+            PhysicsLayers::Ground,
+            PhysicsLayers::Wall,
+            PhysicsLayers::Projectile,
+            PhysicsLayers::Platform,
+            PhysicsLayers::Trigger,
+            PhysicsLayers::Pickup,
+            PhysicsLayers::Hazard,
+        ] {
+            assert!(!matrix.masks(layer).is_empty(), "{:?} has no masks", layer);
+        }
+    }
+}
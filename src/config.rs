@@ -0,0 +1,44 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::{TypePath, TypeUuid},
+    utils::BoxedFuture,
+};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+/// Loads config assets of type `T` from ron files. Every config type gets its own
+/// extension, e.g. `collision.ron`, so bevy can tell which loader to use.
+pub struct RonAssetLoader<T> {
+    extensions: Vec<&'static str>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RonAssetLoader<T> {
+    pub fn new(extensions: &[&'static str]) -> Self {
+        RonAssetLoader {
+            extensions: extensions.to_vec(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> AssetLoader for RonAssetLoader<T>
+where
+    T: DeserializeOwned + TypeUuid + TypePath + Send + Sync + 'static,
+{
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config = ron::de::from_bytes::<T>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &self.extensions
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    enemy::{clock::Clock, Enemy},
+    level::Ground,
+    physics::MAX_SLOPE_NORMAL_Y,
+    player::Player,
+};
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
+use bevy_xpbd_2d::prelude::*;
+
+pub struct ContactsPlugin;
+
+/// This plugin turns the raw collisions of the physics engine into typed gameplay
+/// events, so gameplay systems can react to contacts instead of polling
+/// `CollidingEntities`.
+///
+/// The events are produced right after the physics step and are updated once per fixed
+/// tick rather than once per frame, so none are lost when several frames pass between
+/// two ticks.
+impl Plugin for ContactsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Events<PlayerHitByEnemy>>()
+            .init_resource::<Events<PlayerHitByProjectile>>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        Events::<PlayerHitByEnemy>::update_system,
+                        Events::<PlayerHitByProjectile>::update_system,
+                    ),
+                    classify_contacts,
+                )
+                    .chain()
+                    .after(PhysicsSet::Sync),
+            );
+    }
+}
+
+/// The player touched an enemy
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerHitByEnemy {
+    pub player: Entity,
+    pub enemy: Entity,
+    /// The contact normal, pointing from the enemy towards the player
+    pub normal: Vec2,
+}
+
+/// The player was hit by a projectile (a clock)
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlayerHitByProjectile {
+    pub player: Entity,
+    pub projectile: Entity,
+    /// The contact normal, pointing from the projectile towards the player
+    pub normal: Vec2,
+}
+
+/// The walkable ground a character currently touches. Kept up to date from the
+/// collision events, so checking whether a character is grounded doesn't need to scan
+/// all the ground entities.
+#[derive(Component, Clone, Debug, Default)]
+pub struct GroundContacts(pub HashSet<Entity>);
+
+impl GroundContacts {
+    pub fn is_grounded(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+#[derive(SystemParam)]
+pub struct ContactWriters<'w> {
+    hit_by_enemy: EventWriter<'w, PlayerHitByEnemy>,
+    hit_by_projectile: EventWriter<'w, PlayerHitByProjectile>,
+}
+
+/// Classifies the collisions of the last physics step into gameplay events.
+///
+/// Hits are reported once, when the contact starts. Ground contacts are tracked for as
+/// long as they last.
+pub fn classify_contacts(
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>,
+    mut collisions: EventReader<Collision>,
+    players: Query<(), With<Player>>,
+    enemies: Query<(), With<Enemy>>,
+    projectiles: Query<(), With<Clock>>,
+    grounds: Query<(), With<Ground>>,
+    mut ground_contacts: Query<&mut GroundContacts>,
+    mut writers: ContactWriters,
+) {
+    let started = started
+        .iter()
+        .flat_map(|CollisionStarted(a, b)| [(*a, *b), (*b, *a)])
+        .collect::<HashSet<(Entity, Entity)>>();
+
+    for CollisionEnded(a, b) in ended.iter() {
+        for (entity, other) in [(*a, *b), (*b, *a)] {
+            if let Ok(mut contacts) = ground_contacts.get_mut(entity) {
+                contacts.0.remove(&other);
+            }
+        }
+    }
+
+    for Collision(contact) in collisions.iter() {
+        // the contact normal points from the first entity towards the second one, flip
+        // it so that it always points towards the entity the contact is reported for
+        let pairs = [
+            (contact.entity1, contact.entity2, -contact.normal),
+            (contact.entity2, contact.entity1, contact.normal),
+        ];
+
+        for (entity, other, normal) in pairs {
+            if grounds.contains(other) {
+                if let Ok(mut contacts) = ground_contacts.get_mut(entity) {
+                    if normal.y >= MAX_SLOPE_NORMAL_Y {
+                        contacts.0.insert(other);
+                    } else {
+                        contacts.0.remove(&other);
+                    }
+                }
+            }
+
+            if !started.contains(&(entity, other)) {
+                continue;
+            }
+
+            if players.contains(entity) && enemies.contains(other) {
+                writers.hit_by_enemy.send(PlayerHitByEnemy {
+                    player: entity,
+                    enemy: other,
+                    normal,
+                });
+            }
+
+            if players.contains(entity) && projectiles.contains(other) {
+                writers.hit_by_projectile.send(PlayerHitByProjectile {
+                    player: entity,
+                    projectile: other,
+                    normal,
+                });
+            }
+        }
+    }
+}
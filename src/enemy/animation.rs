@@ -2,6 +2,11 @@ use bevy::prelude::*;
 use rand::random;
 
 use super::{clock::SpewClocks, *};
+use crate::camera::effects::CameraShake;
+use crate::timestep::SimulationTime;
+
+/// How much an enemy lunging right next to the player shakes the camera
+const LUNGE_TRAUMA: f32 = 0.25;
 
 pub fn update_enemy_animation(
     mut sprites: Query<(&mut TextureAtlasSprite, &mut AnimationSettings, &Enemy)>,
@@ -101,7 +106,8 @@ pub fn process_actions(
         &mut Enemy,
         &Position,
     )>,
-    time: Res<Time>,
+    time: Res<SimulationTime>,
+    mut shakes: EventWriter<CameraShake>,
 ) {
     for (entity, mut velocity, mut state, mut enemy, position) in query.iter_mut() {
         let target_delta;
@@ -146,6 +152,13 @@ pub fn process_actions(
                     //lunge
                     velocity.x = if enemy.facing_left { -speed } else { speed };
                     velocity.y = speed;
+
+                    // the closer the lunge, the harder it shakes
+                    if let Some(target) = enemy.target {
+                        shakes.send(CameraShake {
+                            trauma: LUNGE_TRAUMA * danger_level(target.0, [position.0].into_iter()),
+                        });
+                    }
                 } else if before_lunge_timer.finished() && is_grounded {
                     after_lunge_timer.tick(time.delta());
 
@@ -4,10 +4,20 @@ use bevy_xpbd_2d::prelude::*;
 use rand::random;
 
 use crate::{
-    audio::AlarmSoundEffect, loading::ClockTextureAtlasAsset, physics::PhysicsLayers,
+    audio::AlarmSoundEffect,
+    camera::effects::{CameraShake, HitStop},
+    collision_matrix::CollisionMatrix,
+    contacts::PlayerHitByProjectile,
+    items::Inventory,
+    loading::ClockTextureAtlasAsset,
+    physics::PhysicsLayers,
     player::Player,
+    timestep::{Interpolated, SimulationTime},
 };
 
+/// How much a clock going off right next to the player shakes the camera
+const CLOCK_TRAUMA: f32 = 0.2;
+
 #[derive(Component)]
 pub struct Clock {
     pub lifetime: f32,
@@ -21,20 +31,13 @@ pub struct ClockBundle {
     pub collision_layers: CollisionLayers,
 }
 
-impl Default for ClockBundle {
-    fn default() -> Self {
+impl ClockBundle {
+    pub fn new(collision_matrix: &CollisionMatrix) -> Self {
         Self {
             clock: Clock { lifetime: 5.0 },
             rigid_body: RigidBody::Dynamic,
             collider: Collider::ball(9.0),
-            collision_layers: CollisionLayers::new(
-                [PhysicsLayers::Projectile],
-                [
-                    PhysicsLayers::Player,
-                    PhysicsLayers::Ground,
-                    PhysicsLayers::Wall,
-                ],
-            ),
+            collision_layers: collision_matrix.collision_layers(PhysicsLayers::Projectile),
         }
     }
 }
@@ -47,30 +50,57 @@ pub struct SpewClocks {
     pub played_sound: bool,
 }
 
+/// Clocks go off at the end of their lifetime, shaking the camera if they're close to
+/// a player
 pub fn update_clocks(
     mut commands: Commands,
-    time: Res<Time>,
-    mut query: Query<(Entity, &mut Clock)>,
+    time: Res<SimulationTime>,
+    mut query: Query<(Entity, &mut Clock, &Position)>,
+    players: Query<&Position, With<Player>>,
+    mut shakes: EventWriter<CameraShake>,
 ) {
-    for (entity, mut clock) in query.iter_mut() {
+    for (entity, mut clock, position) in query.iter_mut() {
         clock.lifetime -= time.delta_seconds();
         if clock.lifetime <= 0.0 {
             commands.entity(entity).despawn();
+
+            let danger = players
+                .iter()
+                .map(|player| super::danger_level(player.0, [position.0].into_iter()))
+                .fold(0.0, f32::max);
+
+            if danger > 0.0 {
+                shakes.send(CameraShake {
+                    trauma: CLOCK_TRAUMA * danger,
+                });
+            }
         }
     }
 }
 
+/// A clock that hits the player kills them, unless their hook is upgraded to swat the
+/// clock away
 pub fn check_collisions_with_player(
-    mut query: Query<&CollidingEntities, With<Clock>>,
-    mut player_query: Query<(Entity, &mut Player)>,
+    mut commands: Commands,
+    mut hits: EventReader<PlayerHitByProjectile>,
+    mut player_query: Query<&mut Player>,
+    inventory: Res<Inventory>,
+    mut shakes: EventWriter<CameraShake>,
+    mut hit_stops: EventWriter<HitStop>,
 ) {
-    let (player_entity, mut player) = player_query.single_mut();
+    for hit in hits.iter() {
+        if inventory.hook_level >= 1 {
+            commands.entity(hit.projectile).despawn_recursive();
 
-    for entities in query.iter_mut() {
-        for entity in entities.iter() {
-            if entity == &player_entity {
-                player.is_alive = false;
-            }
+            shakes.send(CameraShake { trauma: 0.15 });
+        } else if let Ok(mut player) = player_query.get_mut(hit.player) {
+            player.is_alive = false;
+
+            shakes.send(CameraShake { trauma: 0.6 });
+            hit_stops.send(HitStop {
+                seconds: 0.12,
+                time_scale: 0.1,
+            });
         }
     }
 }
@@ -78,10 +108,11 @@ pub fn check_collisions_with_player(
 pub fn spew_clocks(
     mut commands: Commands,
     mut query: Query<&mut SpewClocks>,
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     clock: Res<ClockTextureAtlasAsset>,
     ticktock: Res<AlarmSoundEffect>,
     mut audio_assets: ResMut<Assets<AudioInstance>>,
+    collision_matrix: Res<CollisionMatrix>,
 ) {
     for mut spew in query.iter_mut() {
         let current_time = time.elapsed_seconds_f64();
@@ -96,9 +127,10 @@ pub fn spew_clocks(
 
         if at_interval(spew.rate_interval) {
             commands
-                .spawn(ClockBundle::default())
+                .spawn(ClockBundle::new(&collision_matrix))
                 .insert(Position(spew.source_position))
                 .insert(LinearVelocity(spew.velocity))
+                .insert(Interpolated::default())
                 .insert(SpriteSheetBundle {
                     texture_atlas: clock.clock_atlas.clone(),
                     sprite: TextureAtlasSprite {
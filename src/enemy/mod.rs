@@ -1,14 +1,47 @@
 use self::animation::AnimationSettings;
+use crate::camera::effects::{CameraShake, HitStop, ZoomPunch};
+use crate::contacts::PlayerHitByEnemy;
+use crate::items::Inventory;
+use crate::level::triggers::{TriggerAction, TriggerEntered};
 use crate::loading::EnemyTextureAtlasAsset;
 use crate::player::Player;
+use crate::shader_utils::Dissolve;
+use crate::timestep::FixedGameplaySet;
 use crate::GameState;
 use crate::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_xpbd_2d::prelude::*;
 use state::EnemyState;
 
+/// The horizontal distance between enemies spawned together by a trigger zone
+const SPAWN_SPACING: f32 = 48.0;
+
+/// How close the nearest enemy has to be to the player to be a danger
+pub const DANGER_DISTANCE: f32 = 320.0;
+
+/// How close the nearest enemy is to the player, from 0 when there is none within
+/// `DANGER_DISTANCE` to 1 when it is right on the player
+pub fn danger_level(player: Vec2, enemies: impl Iterator<Item = Vec2>) -> f32 {
+    // there may be no enemies in the rooms that are loaded
+    let shortest_distance = enemies
+        .map(|enemy| enemy.distance(player))
+        .min_by(|a, b| a.total_cmp(b))
+        .unwrap_or(f32::MAX);
+
+    ((DANGER_DISTANCE - shortest_distance) / DANGER_DISTANCE).clamp(0.0, 1.0)
+}
+
+/// The position of the living player closest to an enemy, who the enemy goes after
+pub fn nearest_player(position: Vec2, players: &Query<(&Position, &Player)>) -> Option<Position> {
+    players
+        .iter()
+        .filter(|(_, player)| player.is_alive)
+        .map(|(player_position, _)| *player_position)
+        .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+}
+
 mod animation;
-mod clock;
+pub mod clock;
 mod state;
 
 pub struct EnemyPlugin;
@@ -27,9 +60,18 @@ impl Plugin for EnemyPlugin {
             .add_systems(
                 Update,
                 (
-                    animation::process_actions,
                     animation::animation_controller,
                     animation::update_enemy_animation,
+                    spawn_enemies_from_triggers,
+                    // enemies of rooms loaded as neighbours
+                    initialize_enemies,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    animation::process_actions,
                     animation::process_state_change,
                     state::attack_state_system,
                     state::patrol_pursue_state_system,
@@ -38,7 +80,7 @@ impl Plugin for EnemyPlugin {
                     clock::check_collisions_with_player,
                     clock::spew_clocks,
                 )
-                    .run_if(in_state(GameState::Playing)),
+                    .in_set(FixedGameplaySet),
             )
             .add_systems(OnExit(GameState::Playing), (cleanup, clock::cleanup));
     }
@@ -113,47 +155,106 @@ pub struct EnemyLdtkBundle {
 /// takes place here.
 fn initialize_enemies(
     mut commands: Commands,
-    query: Query<(Entity, &Transform), With<Enemy>>,
+    query: Query<(Entity, &Transform), (With<Enemy>, Without<AnimationSettings>)>,
     enemy: Res<EnemyTextureAtlasAsset>,
 ) {
     for (entity, transform) in query.iter() {
         commands
             .entity(entity)
-            .insert((
-                SpriteSheetBundle {
-                    texture_atlas: enemy.enemy_atlas.clone(),
-                    sprite: TextureAtlasSprite::default(),
-                    transform: *transform,
-                    ..default()
-                },
-                AnimationSettings {
-                    frames: vec![0, 1],
-                    animation_timer: AnimationTimer {
-                        timer: Timer::from_seconds(0.125, TimerMode::Repeating),
-                        frame_count: 2,
-                    },
-                    last_state: EnemyState::Patrol,
-                },
-            ))
-            .insert(Name::new("enemy"))
-            .insert(EnemyState::Patrol)
-            .insert(physics::InitSpriteRigidBody::Dynamic);
+            .insert(enemy_components(&enemy, *transform));
+    }
+}
+
+/// The components that make an `Enemy` a sprite with animations and physics
+fn enemy_components(enemy: &EnemyTextureAtlasAsset, transform: Transform) -> impl Bundle {
+    (
+        SpriteSheetBundle {
+            texture_atlas: enemy.enemy_atlas.clone(),
+            sprite: TextureAtlasSprite::default(),
+            transform,
+            ..default()
+        },
+        AnimationSettings {
+            frames: vec![0, 1],
+            animation_timer: AnimationTimer {
+                timer: Timer::from_seconds(0.125, TimerMode::Repeating),
+                frame_count: 2,
+            },
+            last_state: EnemyState::Patrol,
+        },
+        Name::new("enemy"),
+        EnemyState::Patrol,
+        physics::InitSpriteRigidBody::Dynamic,
+    )
+}
+
+/// Spawns the enemies of `SpawnEnemies` trigger zones, side by side at the center of
+/// the zone. Their physics is set up by `init_sprite_physics` on the next frame.
+fn spawn_enemies_from_triggers(
+    mut commands: Commands,
+    mut triggers: EventReader<TriggerEntered>,
+    zones: Query<&GlobalTransform>,
+    enemy: Res<EnemyTextureAtlasAsset>,
+) {
+    for event in triggers.iter() {
+        let Some(TriggerAction::SpawnEnemies(count)) = event.action else {
+            continue;
+        };
+
+        let Ok(zone_transform) = zones.get(event.trigger) else {
+            continue;
+        };
+
+        let center = zone_transform.translation();
+        let first = -(count.saturating_sub(1) as f32) * SPAWN_SPACING / 2.0;
+
+        for i in 0..count {
+            let translation = center + Vec3::X * (first + i as f32 * SPAWN_SPACING);
+
+            commands.spawn((
+                Enemy::default(),
+                enemy_components(&enemy, Transform::from_translation(translation)),
+            ));
+        }
     }
 }
 
 /// This system will check for collisions with the player. If the player
-/// is hit, the player is killed.
+/// is hit, the player is killed. With a fully upgraded hook, landing on an enemy
+/// defeats it instead.
 pub fn check_collisions_with_player(
-    query: Query<&CollidingEntities, &Enemy>,
-    mut player: Query<(Entity, &mut Player)>,
+    mut commands: Commands,
+    mut hits: EventReader<PlayerHitByEnemy>,
+    mut players: Query<&mut Player>,
+    inventory: Res<Inventory>,
+    mut shakes: EventWriter<CameraShake>,
+    mut zoom_punches: EventWriter<ZoomPunch>,
+    mut hit_stops: EventWriter<HitStop>,
 ) {
-    let (player_entity, mut player) = player.single_mut();
+    for hit in hits.iter() {
+        if inventory.hook_level >= 2 && hit.normal.y >= physics::MAX_SLOPE_NORMAL_Y {
+            commands
+                .entity(hit.enemy)
+                .remove::<(Enemy, RigidBody, Collider)>()
+                .insert(Dissolve::default());
+
+            shakes.send(CameraShake { trauma: 0.3 });
+            zoom_punches.send(ZoomPunch {
+                amount: 0.08,
+                seconds: 0.25,
+            });
+            hit_stops.send(HitStop {
+                seconds: 0.08,
+                time_scale: 0.1,
+            });
+        } else if let Ok(mut player) = players.get_mut(hit.player) {
+            player.is_alive = false;
 
-    for colliding_entities in query.iter() {
-        for entity in colliding_entities.iter() {
-            if entity == &player_entity {
-                player.is_alive = false;
-            }
+            shakes.send(CameraShake { trauma: 0.6 });
+            hit_stops.send(HitStop {
+                seconds: 0.12,
+                time_scale: 0.1,
+            });
         }
     }
 }
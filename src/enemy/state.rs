@@ -16,21 +16,21 @@ pub enum EnemyState {
     SpewAttack,
 }
 
-/// This checks to see if the player is within the `patrol_range` of the enemy.
+/// This checks to see if the nearest living player is within the `patrol_range` of the
+/// enemy.
 /// - If so, it sets the `EnemyState` to `Pursue`.
 /// - If not, it sets the `EnemyState` to `Patrol`.
 pub fn patrol_pursue_state_system(
     mut query: Query<(&Position, &mut EnemyState, &mut Enemy), Without<Player>>,
-    player: Query<&Position, With<Player>>,
+    players: Query<(&Position, &Player)>,
 ) {
-    let player_position = player.single();
-
     for (position, mut state, mut enemy) in query.iter_mut() {
-        let distance = player_position.distance(position.0);
+        let player_position = nearest_player(position.0, &players);
+        let distance = player_position.map_or(f32::MAX, |player| player.distance(position.0));
 
         match (*state, distance) {
             (EnemyState::Patrol, d) if d <= enemy.patrol_range => {
-                enemy.target = Some(*player_position);
+                enemy.target = player_position;
                 *state = EnemyState::Pursue;
             }
             (EnemyState::Pursue, d) if d > enemy.patrol_range => {
@@ -42,20 +42,20 @@ pub fn patrol_pursue_state_system(
     }
 }
 
-/// This checks to see if the player is within attack range of the enemies. If so,
-/// it sets the `EnemyState` to a random attack state using `get_random_attack_state()`.
+/// This checks to see if the nearest living player is within attack range of the
+/// enemies. If so, it sets the `EnemyState` to a random attack state using
+/// `get_random_attack_state()`.
 pub fn attack_state_system(
     mut query: Query<(&Position, &mut EnemyState, &mut Enemy), Without<Player>>,
-    player: Query<&Position, With<Player>>,
+    players: Query<(&Position, &Player)>,
 ) {
-    let player_position = player.single();
-
     for (position, mut state, mut enemy) in query.iter_mut() {
-        let distance = player_position.distance(position.0);
+        let player_position = nearest_player(position.0, &players);
+        let distance = player_position.map_or(f32::MAX, |player| player.distance(position.0));
 
         match (*state, distance) {
             (EnemyState::Patrol | EnemyState::Pursue, d) if d < enemy.attack_range => {
-                enemy.target = Some(*player_position);
+                enemy.target = player_position;
                 *state = get_random_attack_state();
             }
             _ => {} // do nothing if not in range.
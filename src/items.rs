@@ -0,0 +1,231 @@
+use crate::{
+    actions::Actions, level::fields, physics::InitSpriteRigidBody, player::Player,
+    timestep::FixedGameplaySet, GameState,
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// How close the player has to be to a chest to open it
+const CHEST_REACH: f32 = 32.0;
+
+/// How close the player has to be to a pickup to collect it
+const PICKUP_RADIUS: f32 = 20.0;
+
+/// The size of a pickup's sprite and collider
+const PICKUP_SIZE: Vec2 = Vec2::new(12.0, 12.0);
+
+/// How fast an item jumps out of an opened chest
+const CHEST_LAUNCH_SPEED: f32 = 200.0;
+
+pub struct ItemsPlugin;
+
+/// This plugin handles chests, the items they contain and the player's inventory.
+/// `Chest` and `Item` entities are spawned from the ldtk level, both with an `item`
+/// field naming the `Item`.
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inventory>()
+            .register_ldtk_entity::<ChestBundle>("Chest")
+            .register_ldtk_entity::<PickupBundle>("Item")
+            .add_systems(OnExit(GameState::Menu), reset_inventory)
+            .add_systems(
+                FixedUpdate,
+                (open_chests, collect_pickups).in_set(FixedGameplaySet),
+            )
+            .add_systems(Update, spawn_pickup_sprites)
+            .add_systems(OnExit(GameState::Playing), cleanup);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum Item {
+    #[default]
+    ExtraLife,
+    DoubleJump,
+    HookUpgrade,
+}
+
+impl Item {
+    pub fn from_entity_instance(entity_instance: &EntityInstance) -> Self {
+        match fields::string_field(entity_instance, "item").as_deref() {
+            Some("DoubleJump") => Item::DoubleJump,
+            Some("HookUpgrade") => Item::HookUpgrade,
+            Some("ExtraLife") | None => Item::ExtraLife,
+            Some(item) => {
+                warn!("unknown item {:?}", item);
+                Item::ExtraLife
+            }
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Item::ExtraLife => Color::rgb(0.9, 0.2, 0.3),
+            Item::DoubleJump => Color::rgb(0.3, 0.8, 0.9),
+            Item::HookUpgrade => Color::rgb(0.9, 0.8, 0.2),
+        }
+    }
+}
+
+/// What the player has collected during this run
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Inventory {
+    /// Extra lives, each one respawns the player at the last checkpoint instead of
+    /// ending the game
+    pub lives: u32,
+    /// Whether the player can jump once more in the air
+    pub double_jump: bool,
+    /// The hook swats clocks away from level 1 and defeats enemies that are landed on
+    /// from level 2
+    pub hook_level: u32,
+}
+
+impl Inventory {
+    pub fn add(&mut self, item: Item) {
+        match item {
+            Item::ExtraLife => self.lives += 1,
+            Item::DoubleJump => self.double_jump = true,
+            Item::HookUpgrade => self.hook_level += 1,
+        }
+    }
+
+    /// How often the player can jump in the air
+    pub fn air_jumps(&self) -> u32 {
+        u32::from(self.double_jump)
+    }
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Chest {
+    pub item: Item,
+    pub opened: bool,
+}
+
+impl From<&EntityInstance> for Chest {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Chest {
+            item: Item::from_entity_instance(entity_instance),
+            opened: false,
+        }
+    }
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct ChestBundle {
+    #[from_entity_instance]
+    chest: Chest,
+    #[sprite_sheet_bundle]
+    sprite_sheet_bundle: SpriteSheetBundle,
+}
+
+/// An item lying in the level, collected by walking over it
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Pickup(pub Item);
+
+impl From<&EntityInstance> for Pickup {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Pickup(Item::from_entity_instance(entity_instance))
+    }
+}
+
+fn pickup_collider(_: &EntityInstance) -> Collider {
+    Collider::cuboid(PICKUP_SIZE.x, PICKUP_SIZE.y)
+}
+
+fn dynamic(_: &EntityInstance) -> InitSpriteRigidBody {
+    InitSpriteRigidBody::Dynamic
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct PickupBundle {
+    #[from_entity_instance]
+    pickup: Pickup,
+    #[with(pickup_collider)]
+    collider: Collider,
+    #[with(dynamic)]
+    rigid_body: InitSpriteRigidBody,
+}
+
+fn reset_inventory(mut inventory: ResMut<Inventory>) {
+    *inventory = Inventory::default();
+}
+
+/// Gives pickups a sprite in the color of their item
+fn spawn_pickup_sprites(mut commands: Commands, pickups: Query<(Entity, &Pickup), Added<Pickup>>) {
+    for (entity, pickup) in pickups.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                color: pickup.0.color(),
+                custom_size: Some(PICKUP_SIZE),
+                ..default()
+            },
+            Handle::<Image>::default(),
+            Visibility::default(),
+            ComputedVisibility::default(),
+            Name::new("pickup"),
+        ));
+    }
+}
+
+/// Opens the chest a player stands at when they interact. The item jumps out of the
+/// chest as a pickup.
+fn open_chests(
+    mut commands: Commands,
+    players: Query<(&Actions, &GlobalTransform), With<Player>>,
+    mut chests: Query<(&GlobalTransform, &mut Chest, &mut TextureAtlasSprite)>,
+) {
+    for (transform, mut chest, mut sprite) in chests.iter_mut() {
+        let chest_position = transform.translation().truncate();
+        let opened_by_player = players.iter().any(|(actions, player)| {
+            actions.interact
+                && chest_position.distance(player.translation().truncate()) <= CHEST_REACH
+        });
+
+        if chest.opened || !opened_by_player {
+            continue;
+        }
+
+        chest.opened = true;
+        sprite.color = Color::rgb(0.5, 0.5, 0.5);
+
+        commands.spawn((
+            Pickup(chest.item),
+            Collider::cuboid(PICKUP_SIZE.x, PICKUP_SIZE.y),
+            InitSpriteRigidBody::Dynamic,
+            TransformBundle::from_transform(Transform::from_translation(
+                transform.translation() + Vec3::Y * PICKUP_SIZE.y,
+            )),
+            LinearVelocity(Vec2::Y * CHEST_LAUNCH_SPEED),
+        ));
+    }
+}
+
+/// The players share their inventory, so either of them may collect a pickup
+fn collect_pickups(
+    mut commands: Commands,
+    mut inventory: ResMut<Inventory>,
+    players: Query<&GlobalTransform, With<Player>>,
+    pickups: Query<(Entity, &GlobalTransform, &Pickup)>,
+) {
+    for (entity, transform, pickup) in pickups.iter() {
+        let position = transform.translation().truncate();
+
+        if players
+            .iter()
+            .any(|player| position.distance(player.translation().truncate()) <= PICKUP_RADIUS)
+        {
+            info!("collected {:?}", pickup.0);
+            inventory.add(pickup.0);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Pickups that jumped out of chests aren't part of the level, so they aren't
+/// despawned along with it
+pub fn cleanup(mut commands: Commands, pickups: Query<Entity, (With<Pickup>, Without<Parent>)>) {
+    for entity in pickups.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
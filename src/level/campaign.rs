@@ -0,0 +1,253 @@
+use super::PlayedLdtk;
+use crate::{
+    camera::pixel_perfect::ResolutionMode,
+    loading::{ConfigAssets, LevelAsset},
+    timestep::SimulationTime,
+    GameState,
+};
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::{Duration, HashMap},
+};
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How long the level complete screen is shown unless a key is pressed
+const LEVEL_COMPLETE_SECONDS: f32 = 4.0;
+
+/// Where the save data is stored, in local storage on the web or as a file otherwise
+const SAVE_KEY: &str = "hook_the_game.save.ron";
+
+/// The order of the levels, by their ldtk identifiers. Loaded from
+/// `assets/config/main.campaign.ron`.
+#[derive(Resource, Deserialize, TypeUuid, TypePath, Clone, Debug)]
+#[uuid = "0e5f2b7a-3c41-4d8e-a6b9-2f7c1d9e4a53"]
+pub struct Campaign {
+    pub levels: Vec<String>,
+}
+
+impl Default for Campaign {
+    fn default() -> Self {
+        Campaign {
+            levels: vec!["Level_0".to_string()],
+        }
+    }
+}
+
+/// The campaign level that is being played
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct CampaignProgress {
+    pub current: usize,
+}
+
+/// The progress that is kept between sessions
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SaveData {
+    /// The index of the furthest level that can be played
+    pub unlocked: usize,
+    /// The fastest completion time of each level, in seconds
+    pub best_times: HashMap<String, f32>,
+    /// Turns off screen shake, zoom punches and hit-stop
+    #[serde(default)]
+    pub reduce_motion: bool,
+    #[serde(default)]
+    pub resolution_mode: ResolutionMode,
+}
+
+impl SaveData {
+    pub fn load() -> Self {
+        read_save()
+            .and_then(|save| match ron::from_str(&save) {
+                Ok(save_data) => Some(save_data),
+                Err(error) => {
+                    warn!("could not read the save data: {}", error);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self) {
+        match ron::to_string(self) {
+            Ok(save) => write_save(&save),
+            Err(error) => warn!("could not write the save data: {}", error),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_save() -> Option<String> {
+    local_storage()?.get_item(SAVE_KEY).ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_save(save: &str) {
+    if let Some(storage) = local_storage() {
+        if storage.set_item(SAVE_KEY, save).is_err() {
+            warn!("could not write the save data to local storage");
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_save() -> Option<String> {
+    std::fs::read_to_string(SAVE_KEY).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_save(save: &str) {
+    if let Err(error) = std::fs::write(SAVE_KEY, save) {
+        warn!("could not write the save data: {}", error);
+    }
+}
+
+/// The stats of the level that is being played
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LevelStats {
+    pub time: Duration,
+}
+
+/// The level complete screen
+#[derive(Component)]
+pub struct LevelCompleteScreen {
+    pub timer: Timer,
+}
+
+pub fn apply_campaign(
+    mut commands: Commands,
+    config: Res<ConfigAssets>,
+    campaigns: Res<Assets<Campaign>>,
+) {
+    match campaigns.get(&config.campaign) {
+        Some(campaign) if !campaign.levels.is_empty() => {
+            commands.insert_resource(campaign.clone());
+        }
+        _ => warn!("campaign not loaded, using the built in one"),
+    }
+}
+
+/// Playing from the menu continues with the furthest unlocked level
+pub fn continue_campaign(
+    campaign: Res<Campaign>,
+    save_data: Res<SaveData>,
+    mut progress: ResMut<CampaignProgress>,
+) {
+    progress.current = save_data
+        .unlocked
+        .min(campaign.levels.len().saturating_sub(1));
+}
+
+/// Loads the current campaign level. `apply_campaign` never uses an empty campaign, but
+/// if there's no level to play anyway, the game goes back to the menu.
+pub fn select_campaign_level(
+    campaign: Res<Campaign>,
+    progress: Res<CampaignProgress>,
+    level_asset: Res<LevelAsset>,
+    mut played_ldtk: ResMut<PlayedLdtk>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut stats: ResMut<LevelStats>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = campaign.levels.get(progress.current) else {
+        warn!(
+            "campaign has no level {}, going back to the menu",
+            progress.current
+        );
+        state.set(GameState::Menu);
+        return;
+    };
+
+    played_ldtk.0 = level_asset.level.clone();
+    *level_selection = LevelSelection::Identifier(level.clone());
+    *stats = LevelStats::default();
+}
+
+pub fn track_level_time(time: Res<SimulationTime>, mut stats: ResMut<LevelStats>) {
+    stats.time += time.delta();
+}
+
+/// Unlocks the next level, records the time and shows the stats
+pub fn complete_level(
+    mut commands: Commands,
+    campaign: Res<Campaign>,
+    progress: Res<CampaignProgress>,
+    stats: Res<LevelStats>,
+    mut save_data: ResMut<SaveData>,
+) {
+    let level = &campaign.levels[progress.current];
+    let time = stats.time.as_secs_f32();
+
+    save_data.unlocked = save_data.unlocked.max(progress.current + 1);
+    let best = save_data
+        .best_times
+        .entry(level.clone())
+        .and_modify(|best| *best = best.min(time))
+        .or_insert(time);
+    let text = format!(
+        "{} complete!\nTime: {:.2}s\nBest: {:.2}s",
+        level, time, best
+    );
+    save_data.store();
+
+    commands.spawn((
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 40.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_text_alignment(TextAlignment::Center)
+        .with_style(Style {
+            margin: UiRect::all(Val::Auto),
+            ..default()
+        }),
+        LevelCompleteScreen {
+            timer: Timer::from_seconds(LEVEL_COMPLETE_SECONDS, TimerMode::Once),
+        },
+        Name::new("level complete"),
+    ));
+}
+
+/// Continues with the next level, or the ending at the end of the campaign
+pub fn leave_level_complete_screen(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    campaign: Res<Campaign>,
+    mut progress: ResMut<CampaignProgress>,
+    mut screens: Query<&mut LevelCompleteScreen>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let Ok(mut screen) = screens.get_single_mut() else {
+        return;
+    };
+
+    screen.timer.tick(time.delta());
+
+    if !screen.timer.finished() && keys.get_just_pressed().next().is_none() {
+        return;
+    }
+
+    if progress.current + 1 < campaign.levels.len() {
+        progress.current += 1;
+        state.set(GameState::LoadingLevel);
+    } else {
+        state.set(GameState::PlayingCutScene);
+    }
+}
+
+pub fn cleanup_level_complete_screen(
+    mut commands: Commands,
+    screens: Query<Entity, With<LevelCompleteScreen>>,
+) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,258 @@
+use super::{platforms::OneWayPlatform, slopes::Slope, Ground, Wall};
+use crate::physics::InitSpriteRigidBody;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::{Collider, Friction};
+
+/// The kind of tile a merged collider was built from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TileKind {
+    Ground,
+    Wall,
+}
+
+/// Marks the parent entity that holds all of the merged tile colliders for a layer
+#[derive(Component, Clone, Debug, Default)]
+pub struct TileColliders;
+
+/// A rectangle of tiles in grid coordinates. `min` is inclusive and `max` is exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+impl TileRect {
+    pub fn size(&self) -> IVec2 {
+        self.max - self.min
+    }
+}
+
+/// Greedily merges a set of grid cells into as few rectangles as possible.
+///
+/// Cells are visited bottom to top, left to right. Each unvisited cell starts a new
+/// rectangle which is first grown to the right, then upwards for as long as every
+/// cell of the next row is present and unvisited.
+pub fn merge_tiles(cells: &HashSet<IVec2>) -> Vec<TileRect> {
+    let mut sorted = cells.iter().copied().collect::<Vec<IVec2>>();
+    sorted.sort_by_key(|cell| (cell.y, cell.x));
+
+    let mut visited = HashSet::<IVec2>::new();
+    let mut rects = Vec::new();
+    let available =
+        |cell: IVec2, visited: &HashSet<IVec2>| cells.contains(&cell) && !visited.contains(&cell);
+
+    for start in sorted {
+        if visited.contains(&start) {
+            continue;
+        }
+
+        // grow to the right
+        let mut max_x = start.x + 1;
+        while available(IVec2::new(max_x, start.y), &visited) {
+            max_x += 1;
+        }
+
+        // grow upwards while the whole row is free
+        let mut max_y = start.y + 1;
+        while (start.x..max_x).all(|x| available(IVec2::new(x, max_y), &visited)) {
+            max_y += 1;
+        }
+
+        for y in start.y..max_y {
+            for x in start.x..max_x {
+                visited.insert(IVec2::new(x, y));
+            }
+        }
+
+        rects.push(TileRect {
+            min: start,
+            max: IVec2::new(max_x, max_y),
+        });
+    }
+
+    rects
+}
+
+/// Replaces the per-cell colliders of the `Ground` and `Wall` IntGrid cells with
+/// merged rectangular colliders. Slopes and one-way platforms keep their own shapes.
+///
+/// The merged colliders are spawned as children of a [`TileColliders`] entity, which
+/// is attached to the IntGrid layer the cells belong to. The individual cells keep
+/// their components but lose their `InitSpriteRigidBody`, so `init_sprite_physics`
+/// only sets up the merged colliders.
+pub fn merge_tile_colliders(
+    mut commands: Commands,
+    cells: Query<
+        (Entity, &GridCoords, &Parent, Option<&Ground>, Option<&Wall>),
+        (
+            With<InitSpriteRigidBody>,
+            Or<(With<Ground>, With<Wall>)>,
+            Without<Slope>,
+            Without<OneWayPlatform>,
+        ),
+    >,
+    layers: Query<&LayerMetadata>,
+) {
+    let mut cells_by_layer = HashMap::<(Entity, TileKind), HashSet<IVec2>>::new();
+
+    for (entity, grid_coords, parent, ground, wall) in cells.iter() {
+        let kind = match (ground, wall) {
+            (Some(_), _) => TileKind::Ground,
+            (_, Some(_)) => TileKind::Wall,
+            _ => continue,
+        };
+
+        cells_by_layer
+            .entry((parent.get(), kind))
+            .or_default()
+            .insert(IVec2::new(grid_coords.x, grid_coords.y));
+
+        commands.entity(entity).remove::<InitSpriteRigidBody>();
+    }
+
+    let mut colliders_by_layer = HashMap::<Entity, Entity>::new();
+
+    for ((layer, kind), layer_cells) in cells_by_layer.iter() {
+        let grid_size = layers
+            .get(*layer)
+            .map(|metadata| metadata.grid_size as f32)
+            .unwrap_or(32.0);
+
+        let parent = *colliders_by_layer.entry(*layer).or_insert_with(|| {
+            let parent = commands
+                .spawn((
+                    TileColliders,
+                    SpatialBundle::default(),
+                    Name::new("tile colliders"),
+                ))
+                .id();
+            commands.entity(*layer).add_child(parent);
+            parent
+        });
+
+        let rects = merge_tiles(layer_cells);
+
+        console_log!(
+            "merge_tile_colliders: {:?} {} cells -> {} colliders",
+            kind,
+            layer_cells.len(),
+            rects.len()
+        );
+
+        commands.entity(parent).with_children(|parent| {
+            for rect in rects {
+                let size = rect.size().as_vec2() * grid_size;
+                let center = (rect.min + rect.max).as_vec2() * grid_size / 2.0;
+
+                let mut collider = parent.spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(center.extend(0.0))),
+                    Collider::cuboid(size.x, size.y),
+                    InitSpriteRigidBody::Static,
+                ));
+
+                match kind {
+                    TileKind::Ground => {
+                        collider.insert((Ground, Friction::new(0.1), Name::new("Ground")));
+                    }
+                    TileKind::Wall => {
+                        collider.insert((Wall, Friction::new(0.), Name::new("Wall")));
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(coords: &[(i32, i32)]) -> HashSet<IVec2> {
+        coords.iter().map(|&(x, y)| IVec2::new(x, y)).collect()
+    }
+
+    /// Every cell is covered by exactly one rectangle, and rectangles cover nothing else
+    fn assert_exact_cover(cells: &HashSet<IVec2>, rects: &[TileRect]) {
+        let mut covered = HashSet::new();
+
+        for rect in rects {
+            assert!(rect.size().x > 0 && rect.size().y > 0, "empty {:?}", rect);
+
+            for y in rect.min.y..rect.max.y {
+                for x in rect.min.x..rect.max.x {
+                    let cell = IVec2::new(x, y);
+                    assert!(cells.contains(&cell), "{:?} covers missing {}", rect, cell);
+                    assert!(covered.insert(cell), "{} is covered twice", cell);
+                }
+            }
+        }
+
+        assert_eq!(covered.len(), cells.len());
+    }
+
+    #[test]
+    fn empty_set_has_no_rects() {
+        assert!(merge_tiles(&HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn single_cell() {
+        let cells = cells(&[(3, -2)]);
+        let rects = merge_tiles(&cells);
+
+        assert_eq!(
+            rects,
+            vec![TileRect {
+                min: IVec2::new(3, -2),
+                max: IVec2::new(4, -1),
+            }]
+        );
+    }
+
+    #[test]
+    fn full_rect_is_one_rect() {
+        let cells = (0..4)
+            .flat_map(|x| (0..3).map(move |y| IVec2::new(x, y)))
+            .collect();
+        let rects = merge_tiles(&cells);
+
+        assert_exact_cover(&cells, &rects);
+        assert_eq!(rects.len(), 1);
+    }
+
+    #[test]
+    fn l_shape() {
+        // ##
+        // #
+        // ###
+        let cells = cells(&[(0, 0), (1, 0), (2, 0), (0, 1), (0, 2), (1, 2)]);
+        let rects = merge_tiles(&cells);
+
+        assert_exact_cover(&cells, &rects);
+        assert_eq!(rects.len(), 3);
+    }
+
+    #[test]
+    fn ring_with_hole() {
+        let cells = (0..3)
+            .flat_map(|x| (0..3).map(move |y| IVec2::new(x, y)))
+            .filter(|cell| *cell != IVec2::new(1, 1))
+            .collect();
+        let rects = merge_tiles(&cells);
+
+        assert_exact_cover(&cells, &rects);
+        assert_eq!(rects.len(), 4);
+    }
+
+    #[test]
+    fn scattered_cells() {
+        let cells = cells(&[(0, 0), (2, 0), (5, 5), (6, 5), (5, 6), (-3, 1), (-3, 2)]);
+        let rects = merge_tiles(&cells);
+
+        assert_exact_cover(&cells, &rects);
+    }
+}
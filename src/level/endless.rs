@@ -0,0 +1,133 @@
+use super::{
+    campaign::{LevelCompleteScreen, LevelStats},
+    generator::{generate_level, GeneratorSettings},
+    PlayedLdtk,
+};
+use crate::{loading::LevelAsset, GameState};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+/// How long the stage complete screen is shown unless a key is pressed
+const STAGE_COMPLETE_SECONDS: f32 = 2.0;
+
+/// Whether the campaign's levels are played, or generated stages one after another
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    #[default]
+    Campaign,
+    Endless,
+}
+
+/// The endless run that is being played. Every run has its own seed, the stages of a
+/// run are generated from it.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct EndlessRun {
+    pub seed: u64,
+    pub stage: u32,
+}
+
+pub fn start_endless_run(mut run: ResMut<EndlessRun>) {
+    *run = EndlessRun {
+        seed: rand::random(),
+        stage: 0,
+    };
+    info!("endless run with seed {}", run.seed);
+}
+
+/// Generates the stage into its own ldtk project, a copy of the level asset with only
+/// the generated level in it
+pub fn generate_stage(
+    run: Res<EndlessRun>,
+    level_asset: Res<LevelAsset>,
+    mut ldtk_assets: ResMut<Assets<LdtkAsset>>,
+    mut ldtk_levels: ResMut<Assets<LdtkLevel>>,
+    mut played_ldtk: ResMut<PlayedLdtk>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut stats: ResMut<LevelStats>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let settings = GeneratorSettings {
+        seed: run.seed,
+        stage: run.stage,
+    };
+
+    let Some((ldtk, level)) = ldtk_assets
+        .get(&level_asset.level)
+        .and_then(|ldtk| Some((ldtk, generate_level(&ldtk.project, settings)?)))
+    else {
+        error!("could not generate a level from the level asset");
+        state.set(GameState::Menu);
+        return;
+    };
+
+    let mut project = ldtk.project.clone();
+    project.levels = vec![level.clone()];
+    project.worlds.clear();
+
+    let generated = LdtkAsset {
+        project,
+        tileset_map: ldtk.tileset_map.clone(),
+        level_map: [(
+            level.iid.clone(),
+            ldtk_levels.add(LdtkLevel {
+                level: level.clone(),
+                background_image: None,
+            }),
+        )]
+        .into_iter()
+        .collect(),
+        int_grid_image_handle: ldtk.int_grid_image_handle.clone(),
+    };
+
+    played_ldtk.0 = ldtk_assets.add(generated);
+    *level_selection = LevelSelection::Iid(level.iid);
+    *stats = LevelStats::default();
+}
+
+pub fn complete_stage(mut commands: Commands, run: Res<EndlessRun>, stats: Res<LevelStats>) {
+    commands.spawn((
+        TextBundle::from_section(
+            format!(
+                "Stage {} complete!\nTime: {:.2}s",
+                run.stage + 1,
+                stats.time.as_secs_f32()
+            ),
+            TextStyle {
+                font_size: 40.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_text_alignment(TextAlignment::Center)
+        .with_style(Style {
+            margin: UiRect::all(Val::Auto),
+            ..default()
+        }),
+        LevelCompleteScreen {
+            timer: Timer::from_seconds(STAGE_COMPLETE_SECONDS, TimerMode::Once),
+        },
+        Name::new("stage complete"),
+    ));
+}
+
+/// Continues with the next, harder stage
+pub fn leave_stage_complete_screen(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mut run: ResMut<EndlessRun>,
+    mut screens: Query<&mut LevelCompleteScreen>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let Ok(mut screen) = screens.get_single_mut() else {
+        return;
+    };
+
+    screen.timer.tick(time.delta());
+
+    if !screen.timer.finished() && keys.get_just_pressed().next().is_none() {
+        return;
+    }
+
+    run.stage += 1;
+    state.set(GameState::LoadingLevel);
+}
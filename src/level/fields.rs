@@ -0,0 +1,77 @@
+//! Helpers for reading the custom fields of ldtk entity instances and levels. Missing
+//! fields and fields with a different type read as `None`, so callers can fall back to
+//! defaults.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{
+    ldtk::{FieldInstance, FieldValue, Level},
+    prelude::*,
+};
+
+fn find_field<'a>(
+    field_instances: &'a [FieldInstance],
+    identifier: &str,
+) -> Option<&'a FieldValue> {
+    field_instances
+        .iter()
+        .find(|field_instance| field_instance.identifier == identifier)
+        .map(|field_instance| &field_instance.value)
+}
+
+pub fn field<'a>(entity_instance: &'a EntityInstance, identifier: &str) -> Option<&'a FieldValue> {
+    find_field(&entity_instance.field_instances, identifier)
+}
+
+pub fn float_field(entity_instance: &EntityInstance, identifier: &str) -> Option<f32> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::Float(value)) => *value,
+        Some(FieldValue::Int(value)) => value.map(|value| value as f32),
+        _ => None,
+    }
+}
+
+pub fn int_field(entity_instance: &EntityInstance, identifier: &str) -> Option<i32> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::Int(value)) => *value,
+        _ => None,
+    }
+}
+
+pub fn bool_field(entity_instance: &EntityInstance, identifier: &str) -> Option<bool> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::Bool(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Reads a `String` or an `Enum` field
+pub fn string_field(entity_instance: &EntityInstance, identifier: &str) -> Option<String> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::String(value)) | Some(FieldValue::Enum(value)) => value.clone(),
+        _ => None,
+    }
+}
+
+/// Reads a `Float` array field. Unset values are skipped.
+pub fn floats_field(entity_instance: &EntityInstance, identifier: &str) -> Vec<f32> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::Floats(values)) => values.iter().flatten().copied().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reads a `Point` array field, in grid coordinates. Unset points are skipped.
+pub fn points_field(entity_instance: &EntityInstance, identifier: &str) -> Vec<IVec2> {
+    match field(entity_instance, identifier) {
+        Some(FieldValue::Points(points)) => points.iter().flatten().copied().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reads a `String` or multiline `String` field of a level
+pub fn level_string_field(level: &Level, identifier: &str) -> Option<String> {
+    match find_field(&level.field_instances, identifier) {
+        Some(FieldValue::String(value)) => value.clone(),
+        _ => None,
+    }
+}
@@ -0,0 +1,405 @@
+//! Generates levels from room chunks. The generated level is an ordinary ldtk `Level`,
+//! built from a hand made level of the project, so it is spawned through the same
+//! `LevelPlugin` registrations as the hand made ones: the same bundles, colliders and
+//! enemy AI.
+
+use super::{BACKGROUND, GROUND, WALL};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ecs_ldtk::ldtk::{
+    EntityDefinition, EntityInstance, LayerInstance, LdtkJson, Level, TileInstance, Type,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// The size of a chunk, in cells
+const CHUNK_WIDTH: usize = 8;
+const CHUNK_HEIGHT: usize = 7;
+
+/// The size of a cell in pixels, the grid size of the project's layers
+const TILE_PIXELS: i32 = super::TILE_SIZE as i32;
+
+/// The number of chunks between the start and the exit on the first stage, and at most
+const MIN_CHUNKS: u32 = 2;
+const MAX_CHUNKS: u32 = 12;
+
+/// The chance of an enemy on each enemy slot on the first stage, and at most
+const MIN_ENEMY_CHANCE: f64 = 0.3;
+const MAX_ENEMY_CHANCE: f64 = 0.9;
+
+/// A piece of a level. `W` is a wall, `#` ground, `.` the level's background and ` `
+/// nothing. `P` is the player's spawn, `X` the level exit and `e` a slot that may get an
+/// enemy, all of them on the background.
+struct Chunk([&'static str; CHUNK_HEIGHT]);
+
+static START: Chunk = Chunk([
+    "WWWWWWWW", //
+    "........", //
+    "........", //
+    "........", //
+    "........", //
+    "..P.....", //
+    "########", //
+]);
+
+static EXIT: Chunk = Chunk([
+    "WWWWWWWW", //
+    "........", //
+    "........", //
+    "........", //
+    ".....X..", //
+    "........", //
+    "########", //
+]);
+
+static CHUNKS: [Chunk; 5] = [
+    Chunk([
+        "WWWWWWWW", //
+        ".e...e..", //
+        "........", //
+        "........", //
+        "........", //
+        "........", //
+        "########", //
+    ]),
+    Chunk([
+        "WWWWWWWW", //
+        "...e....", //
+        "........", //
+        "........", //
+        "...####.", //
+        "..#####.", //
+        "########", //
+    ]),
+    Chunk([
+        "WWWWWWWW", //
+        ".....e..", //
+        "........", //
+        "..#.....", //
+        "..#...#.", //
+        "..#...#.", //
+        "########", //
+    ]),
+    Chunk([
+        "WWWWWWWW", //
+        "........", //
+        "..e.....", //
+        ".######.", //
+        "........", //
+        "........", //
+        "########", //
+    ]),
+    Chunk([
+        "WWWWWWWW", //
+        "e.....e.", //
+        "........", //
+        "........", //
+        "###..###", //
+        "###..###", //
+        "########", //
+    ]),
+];
+
+/// What to generate: the same seed and stage always generate the same level
+#[derive(Clone, Copy, Debug)]
+pub struct GeneratorSettings {
+    pub seed: u64,
+    /// Later stages are longer and have more enemies
+    pub stage: u32,
+}
+
+impl GeneratorSettings {
+    fn chunks(&self) -> u32 {
+        (MIN_CHUNKS + self.stage).min(MAX_CHUNKS)
+    }
+
+    fn enemy_chance(&self) -> f64 {
+        (MIN_ENEMY_CHANCE + 0.1 * self.stage as f64).min(MAX_ENEMY_CHANCE)
+    }
+}
+
+/// Generates a level with the layers, tiles and entities of the project's hand made
+/// levels. Returns `None` if the project has no level with an IntGrid and an Entities
+/// layer, or no `Player` to copy.
+pub fn generate_level(project: &LdtkJson, settings: GeneratorSettings) -> Option<Level> {
+    let template = project
+        .levels
+        .iter()
+        .chain(project.worlds.iter().flat_map(|world| world.levels.iter()))
+        .find(|level| {
+            has_layer(level, |layer_type| matches!(layer_type, Type::IntGrid))
+                && has_layer(level, |layer_type| matches!(layer_type, Type::Entities))
+        })?;
+
+    let prototypes = entity_prototypes(project);
+    let player = prototypes.get("Player")?;
+    let exit = prototypes.get("LevelExit").cloned().or_else(|| {
+        project
+            .defs
+            .entities
+            .iter()
+            .find(|entity_def| entity_def.identifier == "LevelExit")
+            .map(|entity_def| entity_from_definition(player, entity_def))
+    });
+    if exit.is_none() {
+        warn!("the project has no LevelExit, the generated level can't be completed");
+    }
+
+    let mut rng = StdRng::seed_from_u64(settings.seed.wrapping_add(settings.stage as u64));
+
+    let mut chunks = vec![&START];
+    chunks.extend((0..settings.chunks()).map(|_| &CHUNKS[rng.gen_range(0..CHUNKS.len())]));
+    chunks.push(&EXIT);
+
+    // a wall on each side of the chunks
+    let width = chunks.len() * CHUNK_WIDTH + 2;
+    let mut cells = vec![0; width * CHUNK_HEIGHT];
+    let mut spawns = Vec::new();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        for (y, row) in chunk.0.iter().enumerate() {
+            for (x, char) in row.chars().enumerate() {
+                let cell = IVec2::new((1 + index * CHUNK_WIDTH + x) as i32, y as i32);
+
+                cells[y * width + cell.x as usize] = match char {
+                    'W' => WALL,
+                    '#' => GROUND,
+                    ' ' => 0,
+                    _ => BACKGROUND,
+                };
+
+                let entity = match char {
+                    'P' => Some(player),
+                    'X' => exit.as_ref(),
+                    'e' if rng.gen_bool(settings.enemy_chance()) => prototypes.get("Enemy"),
+                    _ => None,
+                };
+                if let Some(entity) = entity {
+                    spawns.push((entity, cell));
+                }
+            }
+        }
+    }
+    for y in 0..CHUNK_HEIGHT {
+        cells[y * width] = WALL;
+        cells[y * width + width - 1] = WALL;
+    }
+
+    let uid = project
+        .levels
+        .iter()
+        .chain(project.worlds.iter().flat_map(|world| world.levels.iter()))
+        .map(|level| level.uid)
+        .max()
+        .unwrap_or_default()
+        + 1;
+
+    let mut level = template.clone();
+    level.identifier = format!("Generated_{}", settings.stage);
+    level.iid = format!("generated-{:016x}-{}", settings.seed, settings.stage);
+    level.uid = uid;
+    level.world_x = 0;
+    level.world_y = 0;
+    level.neighbours.clear();
+
+    for layer in level.layer_instances.iter_mut().flatten() {
+        let grid_size = layer.grid_size;
+
+        layer.level_id = uid;
+        layer.c_wid = width as i32;
+        layer.c_hei = CHUNK_HEIGHT as i32;
+        layer.grid_tiles.clear();
+
+        match layer.layer_instance_type {
+            Type::IntGrid => {
+                let tiles = tile_prototypes(layer);
+
+                layer.auto_layer_tiles = cells
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, value)| {
+                        let mut tile = tiles.get(value)?.clone();
+                        tile.px =
+                            IVec2::new((index % width) as i32, (index / width) as i32) * grid_size;
+                        Some(tile)
+                    })
+                    .collect();
+                layer.int_grid_csv = cells.clone();
+            }
+            Type::Entities => {
+                layer.entity_instances = spawns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (prototype, cell))| {
+                        let mut entity = (*prototype).clone();
+                        entity.iid = format!("{}-{}", level.iid, index);
+                        entity.grid = *cell;
+                        entity.px = *cell * grid_size
+                            + (entity.pivot * Vec2::new(entity.width as f32, entity.height as f32))
+                                .as_ivec2();
+                        entity
+                    })
+                    .collect();
+            }
+            _ => {
+                layer.auto_layer_tiles.clear();
+                layer.int_grid_csv.clear();
+            }
+        }
+    }
+
+    level.px_wid = width as i32 * TILE_PIXELS;
+    level.px_hei = CHUNK_HEIGHT as i32 * TILE_PIXELS;
+
+    Some(level)
+}
+
+fn has_layer(level: &Level, is_type: impl Fn(&Type) -> bool) -> bool {
+    level
+        .layer_instances
+        .iter()
+        .flatten()
+        .any(|layer| is_type(&layer.layer_instance_type))
+}
+
+/// The first instance of every entity in the project, copied into generated levels
+fn entity_prototypes(project: &LdtkJson) -> HashMap<String, EntityInstance> {
+    let mut prototypes = HashMap::new();
+
+    for entity in project
+        .levels
+        .iter()
+        .chain(project.worlds.iter().flat_map(|world| world.levels.iter()))
+        .flat_map(|level| level.layer_instances.iter().flatten())
+        .flat_map(|layer| layer.entity_instances.iter())
+    {
+        prototypes
+            .entry(entity.identifier.clone())
+            .or_insert_with(|| entity.clone());
+    }
+
+    prototypes
+}
+
+/// An instance of an entity that isn't placed in any level yet, without fields or a tile
+fn entity_from_definition(
+    prototype: &EntityInstance,
+    entity_def: &EntityDefinition,
+) -> EntityInstance {
+    let mut entity = prototype.clone();
+    entity.identifier = entity_def.identifier.clone();
+    entity.def_uid = entity_def.uid;
+    entity.width = entity_def.width;
+    entity.height = entity_def.height;
+    entity.pivot = Vec2::new(entity_def.pivot_x, entity_def.pivot_y);
+    entity.tags = entity_def.tags.clone();
+    entity.tile = None;
+    entity.field_instances.clear();
+    entity
+}
+
+/// For every IntGrid value, the auto layer tile that is used the most for it
+fn tile_prototypes(layer: &LayerInstance) -> HashMap<i32, TileInstance> {
+    let mut counts = HashMap::<(i32, i32), (usize, &TileInstance)>::new();
+
+    for tile in layer.auto_layer_tiles.iter() {
+        let cell = tile.px / layer.grid_size;
+        let Some(value) = layer
+            .int_grid_csv
+            .get((cell.y * layer.c_wid + cell.x) as usize)
+        else {
+            continue;
+        };
+
+        counts.entry((*value, tile.t)).or_insert((0, tile)).0 += 1;
+    }
+
+    let mut prototypes = HashMap::<i32, (usize, TileInstance)>::new();
+    for ((value, _), (count, tile)) in counts {
+        match prototypes.get(&value) {
+            Some((most, _)) if *most >= count => {}
+            _ => {
+                prototypes.insert(value, (count, tile.clone()));
+            }
+        }
+    }
+
+    prototypes
+        .into_iter()
+        .map(|(value, (_, tile))| (value, tile))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> LdtkJson {
+        serde_json::from_str(include_str!("../../assets/sprites/level.ldtk"))
+            .expect("the ldtk project should parse")
+    }
+
+    fn layer(level: &Level, is_type: impl Fn(&Type) -> bool) -> &LayerInstance {
+        level
+            .layer_instances
+            .iter()
+            .flatten()
+            .find(|layer| is_type(&layer.layer_instance_type))
+            .expect("the generated level should have the layer")
+    }
+
+    #[test]
+    fn same_seed_generates_same_level() {
+        let project = project();
+        let settings = GeneratorSettings { seed: 42, stage: 3 };
+
+        let first = generate_level(&project, settings).unwrap();
+        let second = generate_level(&project, settings).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(first).unwrap(),
+            serde_json::to_value(second).unwrap()
+        );
+    }
+
+    #[test]
+    fn generated_levels_can_be_completed() {
+        let project = project();
+
+        for seed in 0..20 {
+            for stage in [0, 5, 20] {
+                let level = generate_level(&project, GeneratorSettings { seed, stage }).unwrap();
+                let grid = layer(&level, |layer_type| matches!(layer_type, Type::IntGrid));
+                let entities = &layer(&level, |layer_type| matches!(layer_type, Type::Entities))
+                    .entity_instances;
+                let value_at =
+                    |cell: IVec2| grid.int_grid_csv[(cell.y * grid.c_wid + cell.x) as usize];
+
+                // a floor of ground below the whole level
+                let floor = (grid.c_hei - 1) * grid.c_wid;
+                assert!(grid.int_grid_csv[floor as usize..]
+                    .iter()
+                    .all(|value| *value == GROUND || *value == WALL));
+
+                for identifier in ["Player", "LevelExit"] {
+                    let found = entities
+                        .iter()
+                        .filter(|entity| entity.identifier == identifier)
+                        .collect::<Vec<_>>();
+                    assert_eq!(
+                        found.len(),
+                        1,
+                        "{} in seed {} stage {}",
+                        identifier,
+                        seed,
+                        stage
+                    );
+
+                    // standing on the ground, not inside it
+                    let entity = found[0];
+                    let below = entity.grid + IVec2::Y * (entity.height / grid.grid_size);
+                    assert_eq!(value_at(entity.grid), BACKGROUND);
+                    assert_eq!(value_at(below), GROUND);
+                }
+            }
+        }
+    }
+}
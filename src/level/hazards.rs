@@ -0,0 +1,165 @@
+use super::{
+    rooms::{current_room_bounds, level_bounds, CurrentRoom},
+    PlayedLdtk, TILE_SIZE,
+};
+use crate::{
+    physics::{InitCollisionLayer, InitSpriteRigidBody, PhysicsLayers},
+    player::Player,
+};
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// The IntGrid values used for hazard tiles in the ldtk level
+pub const SPIKES: i32 = 11;
+pub const KILL_ZONE: i32 = 12;
+pub const WATER: i32 = 13;
+
+/// How far below the bottom of the current room the player dies
+const OUT_OF_BOUNDS_MARGIN: f32 = 64.0;
+
+/// The speed the player swims up with while jump or up is held
+pub const SWIM_SPEED: f32 = 120.0;
+
+/// The speed the player sinks with in water
+pub const SINK_SPEED: f32 = 40.0;
+
+/// A tile that kills the player on touch
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Hazard {
+    /// Spikes only fill the lower half of their tile
+    #[default]
+    Spikes,
+    KillZone,
+}
+
+/// A tile of water the player swims in
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Water;
+
+/// The water tiles a character is in
+#[derive(Component, Clone, Debug, Default)]
+pub struct WaterContacts(pub HashSet<Entity>);
+
+impl WaterContacts {
+    pub fn is_swimming(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, Bundle)]
+pub struct HazardColliderBundle {
+    pub name: Name,
+    pub collider: Collider,
+    pub sensor: Sensor,
+    pub rigid_body: InitSpriteRigidBody,
+    pub collision_layer: InitCollisionLayer,
+}
+
+impl From<IntGridCell> for HazardColliderBundle {
+    fn from(int_grid_cell: IntGridCell) -> Self {
+        let (name, collider) = match int_grid_cell.value {
+            SPIKES => (
+                "Spikes",
+                Collider::compound(vec![(
+                    Position(Vec2::new(0.0, -TILE_SIZE / 4.0)),
+                    Rotation::default(),
+                    Collider::cuboid(TILE_SIZE - 4.0, TILE_SIZE / 2.0),
+                )]),
+            ),
+            WATER => ("Water", Collider::cuboid(TILE_SIZE, TILE_SIZE)),
+            _ => ("Kill zone", Collider::cuboid(TILE_SIZE, TILE_SIZE)),
+        };
+
+        HazardColliderBundle {
+            name: Name::new(name),
+            collider,
+            sensor: Sensor,
+            rigid_body: InitSpriteRigidBody::Static,
+            collision_layer: InitCollisionLayer(PhysicsLayers::Hazard),
+        }
+    }
+}
+
+fn hazard_from_int_grid_cell(int_grid_cell: IntGridCell) -> Hazard {
+    match int_grid_cell.value {
+        SPIKES => Hazard::Spikes,
+        _ => Hazard::KillZone,
+    }
+}
+
+#[derive(Clone, Debug, Bundle, LdtkIntCell)]
+pub struct HazardBundle {
+    #[with(hazard_from_int_grid_cell)]
+    pub hazard: Hazard,
+    #[from_int_grid_cell]
+    pub hazard_collider_bundle: HazardColliderBundle,
+}
+
+#[derive(Clone, Debug, Bundle, LdtkIntCell)]
+pub struct WaterBundle {
+    pub water: Water,
+    #[from_int_grid_cell]
+    pub hazard_collider_bundle: HazardColliderBundle,
+}
+
+/// Kills the player touching a hazard, and keeps track of the water the player is in
+pub fn touch_hazards(
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>,
+    hazards: Query<(), With<Hazard>>,
+    water: Query<(), With<Water>>,
+    mut players: Query<(&mut Player, &mut WaterContacts)>,
+) {
+    for CollisionStarted(a, b) in started.iter() {
+        for (entity, other) in [(*a, *b), (*b, *a)] {
+            let Ok((mut player, mut water_contacts)) = players.get_mut(entity) else {
+                continue;
+            };
+
+            if hazards.contains(other) {
+                player.is_alive = false;
+            }
+
+            if water.contains(other) {
+                water_contacts.0.insert(other);
+            }
+        }
+    }
+
+    for CollisionEnded(a, b) in ended.iter() {
+        for (entity, other) in [(*a, *b), (*b, *a)] {
+            if let Ok((_, mut water_contacts)) = players.get_mut(entity) {
+                water_contacts.0.remove(&other);
+            }
+        }
+    }
+}
+
+/// Kills the player once they fell below the current room, unless they fell into
+/// another room
+pub fn out_of_bounds_check(
+    played_ldtk: Res<PlayedLdtk>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    current_room: Res<CurrentRoom>,
+    mut players: Query<(&GlobalTransform, &mut Player)>,
+) {
+    let Some((ldtk, bounds)) = ldtk_assets
+        .get(&played_ldtk.0)
+        .and_then(|ldtk| Some((ldtk, current_room_bounds(ldtk, &current_room)?)))
+    else {
+        return;
+    };
+
+    for (transform, mut player) in players.iter_mut() {
+        let position = transform.translation().truncate();
+
+        let in_a_level = ldtk
+            .iter_levels()
+            .any(|level| level_bounds(level).contains(position));
+
+        if !in_a_level && position.y < bounds.min.y - OUT_OF_BOUNDS_MARGIN {
+            player.is_alive = false;
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::hazards::WaterContacts;
+use crate::{contacts::GroundContacts, player::Player};
+use bevy::{asset::HandleId, prelude::*, utils::HashMap};
+use bevy_ecs_ldtk::{ldtk::Level, prelude::*};
+
+/// The levels as they were spawned, to find the ones that changed on a reload
+#[derive(Default)]
+pub struct SpawnedLevels(HashMap<HandleId, Level>);
+
+/// Debug builds with the `dev` feature watch the assets for changes. When `level.ldtk`
+/// is saved in ldtk, bevy_ecs_ldtk would respawn the whole world along with the worldly
+/// player. Instead, every spawned level is compared with its new version and only the
+/// levels that changed are respawned. The player isn't part of any level, so it keeps
+/// its position, velocity and health, and the camera keeps following it.
+///
+/// The tiles and entities of the respawned levels are set up by the systems that
+/// already handle rooms loaded during play: `merge_tile_colliders`,
+/// `init_sprite_physics` and `initialize_enemies` only pick up the entities that aren't
+/// initialized yet, so the levels that didn't change keep their colliders.
+pub fn respawn_changed_levels(
+    mut commands: Commands,
+    mut ldtk_events: EventReader<AssetEvent<LdtkAsset>>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+    levels: Query<(Entity, &Handle<LdtkLevel>)>,
+    level_assets: Res<Assets<LdtkLevel>>,
+    mut spawned_levels: Local<SpawnedLevels>,
+    mut players: Query<(Option<&mut GroundContacts>, Option<&mut WaterContacts>), With<Player>>,
+) {
+    let reloaded = ldtk_events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { .. }));
+
+    if reloaded {
+        for world in worlds.iter() {
+            commands.entity(world).remove::<Respawn>();
+        }
+    }
+
+    let mut respawned = false;
+
+    for (entity, handle) in levels.iter() {
+        let Some(ldtk_level) = level_assets.get(handle) else {
+            continue;
+        };
+
+        match spawned_levels.0.get(&handle.id()) {
+            Some(level) if *level == ldtk_level.level => continue,
+            Some(_) => {
+                info!(
+                    "level {} changed, respawning it",
+                    ldtk_level.level.identifier
+                );
+                commands.entity(entity).insert(Respawn);
+                respawned = true;
+            }
+            None => {}
+        }
+
+        spawned_levels
+            .0
+            .insert(handle.id(), ldtk_level.level.clone());
+    }
+
+    // forget the levels that were despawned, they are compared again once they are back
+    spawned_levels
+        .0
+        .retain(|id, _| levels.iter().any(|(_, handle)| handle.id() == *id));
+
+    if !respawned {
+        return;
+    }
+
+    // the tiles the player touched are despawned without ending the collisions
+    for (ground_contacts, water_contacts) in players.iter_mut() {
+        if let Some(mut ground_contacts) = ground_contacts {
+            ground_contacts.0.clear();
+        }
+        if let Some(mut water_contacts) = water_contacts {
+            water_contacts.0.clear();
+        }
+    }
+}
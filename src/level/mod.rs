@@ -0,0 +1,270 @@
+use crate::{
+    config::RonAssetLoader,
+    physics::{init_sprite_physics, InitSpriteRigidBody},
+    timestep::FixedGameplaySet,
+    GameState,
+};
+use bevy::prelude::*;
+//use bevy_debug_text_overlay::screen_print;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::{Friction, PhysicsSet};
+
+pub mod campaign;
+mod colliders;
+pub mod endless;
+pub mod fields;
+pub mod generator;
+pub mod hazards;
+#[cfg(debug_assertions)]
+mod hot_reload;
+pub mod platforms;
+pub mod rooms;
+pub mod slopes;
+pub mod triggers;
+pub mod validation;
+
+/// The size of a tile in the ldtk level, in pixels
+pub const TILE_SIZE: f32 = 32.0;
+
+/// The IntGrid value of the level's background. It only has an auto layer tile, no
+/// collider.
+pub const BACKGROUND: i32 = 1;
+pub const GROUND: i32 = 2;
+pub const WALL: i32 = 3;
+
+/// The IntGrid values that `LevelPlugin` knows about
+pub const INT_GRID_VALUES: [i32; 13] = [
+    BACKGROUND,
+    GROUND,
+    WALL,
+    slopes::SLOPE_RIGHT_45,
+    slopes::SLOPE_LEFT_45,
+    slopes::SLOPE_RIGHT_SHALLOW_LOW,
+    slopes::SLOPE_RIGHT_SHALLOW_HIGH,
+    slopes::SLOPE_LEFT_SHALLOW_LOW,
+    slopes::SLOPE_LEFT_SHALLOW_HIGH,
+    platforms::ONE_WAY_PLATFORM,
+    hazards::SPIKES,
+    hazards::KILL_ZONE,
+    hazards::WATER,
+];
+
+/// The ldtk project the level is spawned from: the level asset for the campaign, a
+/// generated one in endless mode
+#[derive(Resource, Clone, Debug, Default)]
+pub struct PlayedLdtk(pub Handle<LdtkAsset>);
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(LdtkPlugin)
+            .register_type::<EntityInstance>()
+            .insert_resource(LevelSelection::Index(0))
+            .insert_resource(LdtkSettings {
+                level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                    load_level_neighbors: true,
+                },
+                set_clear_color: SetClearColor::FromLevelBackground,
+                int_grid_rendering: IntGridRendering::Invisible,
+                level_background: LevelBackground::Nonexistent,
+            })
+            .register_ldtk_int_cell::<GroundBundle>(GROUND)
+            .register_ldtk_int_cell::<WallBundle>(WALL)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_RIGHT_45)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_LEFT_45)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_RIGHT_SHALLOW_LOW)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_RIGHT_SHALLOW_HIGH)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_LEFT_SHALLOW_LOW)
+            .register_ldtk_int_cell::<slopes::SlopeBundle>(slopes::SLOPE_LEFT_SHALLOW_HIGH)
+            .register_ldtk_int_cell::<platforms::PlatformBundle>(platforms::ONE_WAY_PLATFORM)
+            .register_ldtk_int_cell::<hazards::HazardBundle>(hazards::SPIKES)
+            .register_ldtk_int_cell::<hazards::HazardBundle>(hazards::KILL_ZONE)
+            .register_ldtk_int_cell::<hazards::WaterBundle>(hazards::WATER)
+            .register_ldtk_entity::<platforms::MovingPlatformBundle>("MovingPlatform")
+            .register_ldtk_entity::<platforms::CrumblingPlatformBundle>("CrumblingPlatform")
+            .register_ldtk_entity::<triggers::TriggerZoneBundle>("TriggerZone")
+            .register_ldtk_entity::<triggers::LevelExitBundle>("LevelExit")
+            .add_event::<triggers::TriggerEntered>()
+            .add_event::<triggers::TriggerStay>()
+            .add_event::<triggers::TriggerExited>()
+            .init_resource::<triggers::Checkpoint>()
+            .add_event::<rooms::RoomChanged>()
+            .init_resource::<rooms::CurrentRoom>()
+            .init_resource::<rooms::RoomTransitionSettings>()
+            .add_asset::<campaign::Campaign>()
+            .add_asset_loader(RonAssetLoader::<campaign::Campaign>::new(&["campaign.ron"]))
+            .init_resource::<campaign::Campaign>()
+            .init_resource::<campaign::CampaignProgress>()
+            .init_resource::<campaign::LevelStats>()
+            .insert_resource(campaign::SaveData::load())
+            .init_resource::<endless::GameMode>()
+            .init_resource::<endless::EndlessRun>()
+            .init_resource::<PlayedLdtk>()
+            .add_systems(OnExit(GameState::Loading), campaign::apply_campaign)
+            .add_systems(OnEnter(GameState::Menu), campaign::continue_campaign)
+            .add_systems(
+                OnExit(GameState::Menu),
+                endless::start_endless_run.run_if(resource_equals(endless::GameMode::Endless)),
+            )
+            .add_systems(
+                OnEnter(GameState::LoadingLevel),
+                (
+                    campaign::select_campaign_level
+                        .run_if(resource_equals(endless::GameMode::Campaign)),
+                    endless::generate_stage.run_if(resource_equals(endless::GameMode::Endless)),
+                    rooms::reset_current_room,
+                    setup,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(GameState::InitializingPhysics),
+                (colliders::merge_tile_colliders, apply_deferred)
+                    .chain()
+                    .before(init_sprite_physics),
+            )
+            // rooms loaded as neighbours during play
+            .add_systems(
+                Update,
+                (colliders::merge_tile_colliders, apply_deferred)
+                    .chain()
+                    .before(init_sprite_physics)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    platforms::drop_through_platforms,
+                    platforms::filter_platform_collisions,
+                    platforms::move_platforms,
+                    platforms::carry_riders,
+                    platforms::crumble_platforms,
+                )
+                    .chain()
+                    .in_set(FixedGameplaySet),
+            )
+            .add_systems(
+                FixedUpdate,
+                (campaign::track_level_time, hazards::out_of_bounds_check).in_set(FixedGameplaySet),
+            )
+            .add_systems(
+                FixedUpdate,
+                (triggers::update_trigger_zones, hazards::touch_hazards)
+                    .after(PhysicsSet::Sync)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    triggers::run_level_trigger_actions,
+                    triggers::update_dialogue,
+                    rooms::follow_player_between_rooms,
+                    rooms::despawn_outside_loaded_rooms,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::Playing), (cleanup, triggers::cleanup))
+            .add_systems(
+                OnEnter(GameState::LevelComplete),
+                (
+                    campaign::complete_level.run_if(resource_equals(endless::GameMode::Campaign)),
+                    endless::complete_stage.run_if(resource_equals(endless::GameMode::Endless)),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    campaign::leave_level_complete_screen
+                        .run_if(resource_equals(endless::GameMode::Campaign)),
+                    endless::leave_stage_complete_screen
+                        .run_if(resource_equals(endless::GameMode::Endless)),
+                )
+                    .run_if(in_state(GameState::LevelComplete)),
+            )
+            .add_systems(
+                OnExit(GameState::LevelComplete),
+                campaign::cleanup_level_complete_screen,
+            );
+
+        // runs after bevy_ecs_ldtk marks the world for a respawn, and before it processes
+        // the levels
+        #[cfg(debug_assertions)]
+        app.add_systems(
+            PreUpdate,
+            hot_reload::respawn_changed_levels
+                .after(bevy_ecs_ldtk::systems::process_ldtk_assets)
+                .before(bevy_ecs_ldtk::systems::process_ldtk_levels),
+        );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    played_ldtk: Res<PlayedLdtk>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    //screen_print!("level setup");
+
+    commands.spawn((
+        LdtkWorldBundle {
+            ldtk_handle: played_ldtk.0.clone(),
+
+            ..default()
+        },
+        Name::new("level"),
+    ));
+
+    state.set(GameState::SpawningEntities);
+}
+
+fn cleanup(levels: Query<Entity, With<LevelSet>>, mut commands: Commands) {
+    for level in &levels {
+        commands.entity(level).despawn();
+    }
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Ground;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Wall;
+
+#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
+pub struct ColliderTileBundle {
+    pub name: Name,
+    pub rigid_body: InitSpriteRigidBody,
+    pub friction: Friction,
+}
+
+#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
+pub struct GroundBundle {
+    pub ground: Ground,
+    #[from_int_grid_cell]
+    pub collider_tile_bundle: ColliderTileBundle,
+}
+
+#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
+pub struct WallBundle {
+    pub wall: Wall,
+    #[from_int_grid_cell]
+    pub collider_tile_bundle: ColliderTileBundle,
+}
+
+impl From<IntGridCell> for ColliderTileBundle {
+    fn from(int_grid_cell: IntGridCell) -> Self {
+        match int_grid_cell.value {
+            GROUND => ColliderTileBundle {
+                name: Name::new("Ground"),
+                rigid_body: InitSpriteRigidBody::Static,
+                friction: Friction::new(0.1),
+            },
+            WALL => ColliderTileBundle {
+                name: Name::new("Wall"),
+                rigid_body: InitSpriteRigidBody::Static,
+                friction: Friction::new(0.),
+            },
+            _ => ColliderTileBundle::default(),
+        }
+    }
+}
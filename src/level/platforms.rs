@@ -0,0 +1,424 @@
+use super::{fields, Ground, TILE_SIZE};
+use crate::{
+    actions::Actions,
+    character_controller::KinematicController,
+    contacts::GroundContacts,
+    physics::{InitSpriteRigidBody, PhysicsLayers},
+    player::Player,
+    timestep::SimulationTime,
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// The IntGrid value used for one-way platform tiles in the ldtk level
+pub const ONE_WAY_PLATFORM: i32 = 10;
+
+/// How thick the solid top of a one-way platform tile is
+pub const PLATFORM_THICKNESS: f32 = TILE_SIZE / 4.0;
+
+/// How far a character's feet may sink into a platform and still stand on it
+const PLATFORM_TOLERANCE: f32 = 2.0;
+
+/// How long the player ignores platforms after pressing down
+const DROP_THROUGH_SECONDS: f32 = 0.25;
+
+pub const MOVING_PLATFORM_SPEED: f32 = 60.0;
+pub const CRUMBLE_SECONDS: f32 = 0.5;
+pub const RESPAWN_SECONDS: f32 = 3.0;
+
+/// A platform that can be jumped up through, and dropped down through by pressing down
+#[derive(Component, Clone, Debug, Default)]
+pub struct OneWayPlatform;
+
+/// Added to characters that interact with platforms: they can be carried by moving
+/// platforms, and can drop down through one-way platforms.
+#[derive(Component, Clone, Debug)]
+pub struct PlatformRider {
+    pub drop_through: Timer,
+    /// The velocity of the moving platform carrying the character, given to the
+    /// character when it jumps or walks off
+    pub carrier_velocity: Vec2,
+}
+
+impl Default for PlatformRider {
+    fn default() -> Self {
+        let mut drop_through = Timer::from_seconds(DROP_THROUGH_SECONDS, TimerMode::Once);
+        drop_through.tick(drop_through.duration());
+
+        PlatformRider {
+            drop_through,
+            carrier_velocity: Vec2::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Bundle)]
+pub struct PlatformColliderBundle {
+    pub name: Name,
+    pub platform: OneWayPlatform,
+    pub collider: Collider,
+    pub rigid_body: InitSpriteRigidBody,
+    pub friction: Friction,
+}
+
+impl From<IntGridCell> for PlatformColliderBundle {
+    fn from(_: IntGridCell) -> Self {
+        PlatformColliderBundle {
+            name: Name::new("Platform"),
+            platform: OneWayPlatform,
+            // only the top of the tile is solid
+            collider: Collider::compound(vec![(
+                Position(Vec2::new(0.0, (TILE_SIZE - PLATFORM_THICKNESS) / 2.0)),
+                Rotation::default(),
+                Collider::cuboid(TILE_SIZE, PLATFORM_THICKNESS),
+            )]),
+            rigid_body: InitSpriteRigidBody::Static,
+            friction: Friction::new(0.1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Bundle, LdtkIntCell)]
+pub struct PlatformBundle {
+    pub ground: Ground,
+    #[from_int_grid_cell]
+    pub platform_collider_bundle: PlatformColliderBundle,
+}
+
+/// Starts dropping a player through one-way platforms when they press down
+pub fn drop_through_platforms(mut riders: Query<(&Actions, &mut PlatformRider), With<Player>>) {
+    for (actions, mut rider) in riders.iter_mut() {
+        if actions.drop_down {
+            rider.drop_through.reset();
+        }
+    }
+}
+
+/// Filters collisions between characters and one-way platforms. A character only
+/// collides with platforms while it is falling (or standing) relative to the moving
+/// platform carrying it, is not dropping through, and is not already overlapping a
+/// platform from below.
+pub fn filter_platform_collisions(
+    time: Res<SimulationTime>,
+    mut riders: Query<(
+        &mut PlatformRider,
+        &LinearVelocity,
+        &ColliderAabb,
+        &mut CollisionLayers,
+        &GroundContacts,
+        Option<&KinematicController>,
+    )>,
+    platforms: Query<&ColliderAabb, (With<OneWayPlatform>, Without<PlatformRider>)>,
+    moving_platforms: Query<(Entity, &LinearVelocity), With<MovingPlatform>>,
+) {
+    for (mut rider, velocity, aabb, mut layers, ground_contacts, controller) in riders.iter_mut() {
+        rider.drop_through.tick(time.delta());
+
+        let feet = aabb.mins.y;
+        let head = aabb.maxs.y;
+
+        let inside_platform = platforms.iter().any(|platform| {
+            let overlaps_x = platform.mins.x < aabb.maxs.x && platform.maxs.x > aabb.mins.x;
+            let top = platform.maxs.y;
+
+            overlaps_x && top > feet + PLATFORM_TOLERANCE && platform.mins.y < head
+        });
+
+        // a rider carried upwards by a moving platform isn't jumping, so its velocity
+        // is compared with the platform's
+        let carrier_velocity = moving_platforms
+            .iter()
+            .find(|(entity, _)| is_rider_on(ground_contacts, controller, *entity))
+            .map_or(Vec2::ZERO, |(_, velocity)| velocity.0);
+
+        let blocked = velocity.y - carrier_velocity.y <= 0.0
+            && rider.drop_through.finished()
+            && !inside_platform;
+
+        *layers = if blocked {
+            layers.add_mask(PhysicsLayers::Platform)
+        } else {
+            layers.remove_mask(PhysicsLayers::Platform)
+        };
+    }
+}
+
+/// How a moving platform continues once it reaches the last point of its path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathMode {
+    /// Reverse back along the path
+    #[default]
+    PingPong,
+    /// Go from the last point straight back to the first one
+    Loop,
+}
+
+/// A kinematic platform that follows a path of points. Created from the `MovingPlatform`
+/// ldtk entity, which uses the fields:
+/// - `path`: Point array, the points to visit after the platform's own position
+/// - `speed`: Float, in pixels per second
+/// - `mode`: String or Enum, `PingPong` or `Loop`
+#[derive(Component, Clone, Debug, Default)]
+pub struct MovingPlatform {
+    /// The path points relative to the platform's starting position
+    pub path: Vec<Vec2>,
+    pub speed: f32,
+    pub mode: PathMode,
+    pub next: usize,
+    pub forward: bool,
+    /// The world position of the first path point, set once physics is running
+    pub origin: Option<Vec2>,
+    pub last_position: Option<Vec2>,
+}
+
+impl From<&EntityInstance> for MovingPlatform {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let mut path = vec![Vec2::ZERO];
+
+        // ldtk points are in grid coordinates with y pointing down
+        path.extend(
+            fields::points_field(entity_instance, "path")
+                .into_iter()
+                .map(|point| {
+                    let offset = (point - entity_instance.grid).as_vec2() * TILE_SIZE;
+                    Vec2::new(offset.x, -offset.y)
+                }),
+        );
+
+        let mode = match fields::string_field(entity_instance, "mode").as_deref() {
+            Some("Loop") => PathMode::Loop,
+            _ => PathMode::PingPong,
+        };
+
+        MovingPlatform {
+            path,
+            speed: fields::float_field(entity_instance, "speed").unwrap_or(MOVING_PLATFORM_SPEED),
+            mode,
+            next: 1,
+            forward: true,
+            origin: None,
+            last_position: None,
+        }
+    }
+}
+
+impl MovingPlatform {
+    /// Picks the index of the path point to head to after the current one was reached
+    fn advance(&mut self) {
+        let len = self.path.len();
+
+        match self.mode {
+            PathMode::Loop => self.next = (self.next + 1) % len,
+            PathMode::PingPong => {
+                if self.forward && self.next + 1 >= len {
+                    self.forward = false;
+                } else if !self.forward && self.next == 0 {
+                    self.forward = true;
+                }
+
+                self.next = if self.forward {
+                    self.next + 1
+                } else {
+                    self.next - 1
+                };
+            }
+        }
+    }
+}
+
+/// A platform that breaks a while after a character lands on it and comes back later.
+/// Created from the `CrumblingPlatform` ldtk entity, which uses the Float fields
+/// `crumble_delay` and `respawn_delay`, in seconds.
+#[derive(Component, Clone, Debug)]
+pub struct CrumblingPlatform {
+    pub size: Vec2,
+    pub crumble_timer: Timer,
+    pub respawn_timer: Timer,
+    pub state: CrumbleState,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CrumbleState {
+    #[default]
+    Solid,
+    Crumbling,
+    Broken,
+}
+
+impl From<&EntityInstance> for CrumblingPlatform {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        CrumblingPlatform {
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+            crumble_timer: Timer::from_seconds(
+                fields::float_field(entity_instance, "crumble_delay").unwrap_or(CRUMBLE_SECONDS),
+                TimerMode::Once,
+            ),
+            respawn_timer: Timer::from_seconds(
+                fields::float_field(entity_instance, "respawn_delay").unwrap_or(RESPAWN_SECONDS),
+                TimerMode::Once,
+            ),
+            state: CrumbleState::Solid,
+        }
+    }
+}
+
+fn collider_from_entity_instance(entity_instance: &EntityInstance) -> Collider {
+    Collider::cuboid(entity_instance.width as f32, entity_instance.height as f32)
+}
+
+fn kinematic(_: &EntityInstance) -> InitSpriteRigidBody {
+    InitSpriteRigidBody::Kinematic
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct MovingPlatformBundle {
+    ground: Ground,
+    #[from_entity_instance]
+    moving_platform: MovingPlatform,
+    #[with(collider_from_entity_instance)]
+    collider: Collider,
+    #[with(kinematic)]
+    rigid_body: InitSpriteRigidBody,
+    #[sprite_sheet_bundle]
+    sprite_sheet_bundle: SpriteSheetBundle,
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct CrumblingPlatformBundle {
+    ground: Ground,
+    #[from_entity_instance]
+    crumbling_platform: CrumblingPlatform,
+    #[with(collider_from_entity_instance)]
+    collider: Collider,
+    rigid_body: InitSpriteRigidBody,
+    #[sprite_sheet_bundle]
+    sprite_sheet_bundle: SpriteSheetBundle,
+}
+
+/// Whether a character is standing on the platform `entity`. Kinematic characters know
+/// their ground from the controller, dynamic ones from their ground contacts.
+fn is_rider_on(
+    ground_contacts: &GroundContacts,
+    controller: Option<&KinematicController>,
+    entity: Entity,
+) -> bool {
+    match controller {
+        Some(controller) => controller.ground == Some(entity),
+        None => ground_contacts.0.contains(&entity),
+    }
+}
+
+/// Drives the moving platforms along their paths by setting their velocity
+pub fn move_platforms(
+    time: Res<SimulationTime>,
+    mut platforms: Query<(&mut MovingPlatform, &Position, &mut LinearVelocity)>,
+) {
+    for (mut platform, position, mut velocity) in platforms.iter_mut() {
+        if platform.path.len() < 2 {
+            velocity.0 = Vec2::ZERO;
+            continue;
+        }
+
+        let origin = *platform.origin.get_or_insert(position.0);
+        let mut delta = origin + platform.path[platform.next] - position.0;
+        let step = platform.speed * time.delta_seconds();
+
+        // head for the next point right away instead of overshooting the reached one
+        if delta.length() <= step {
+            platform.advance();
+            delta = origin + platform.path[platform.next] - position.0;
+        }
+
+        velocity.0 = delta.normalize_or_zero() * platform.speed;
+    }
+}
+
+/// Moves every character standing on a moving platform along with it, so they
+/// inherit the platform's velocity without fighting their own movement code. Once
+/// they jump or walk off, the platform's velocity is added to theirs so they keep the
+/// momentum.
+pub fn carry_riders(
+    mut platforms: Query<(Entity, &mut MovingPlatform, &Position, &LinearVelocity)>,
+    mut riders: Query<
+        (
+            &mut PlatformRider,
+            &mut Position,
+            &mut LinearVelocity,
+            &GroundContacts,
+            Option<&KinematicController>,
+        ),
+        Without<MovingPlatform>,
+    >,
+) {
+    let carriers: Vec<_> = platforms
+        .iter_mut()
+        .map(|(entity, mut platform, position, velocity)| {
+            let delta = platform
+                .last_position
+                .replace(position.0)
+                .map_or(Vec2::ZERO, |last_position| position.0 - last_position);
+
+            (entity, delta, velocity.0)
+        })
+        .collect();
+
+    for (mut rider, mut position, mut velocity, ground_contacts, controller) in riders.iter_mut() {
+        let carrier = carriers
+            .iter()
+            .find(|(entity, ..)| is_rider_on(ground_contacts, controller, *entity));
+
+        match carrier {
+            Some((_, delta, carrier_velocity)) => {
+                position.0 += *delta;
+                rider.carrier_velocity = *carrier_velocity;
+            }
+            None => velocity.0 += std::mem::take(&mut rider.carrier_velocity),
+        }
+    }
+}
+
+/// Breaks crumbling platforms a while after the player lands on them, and respawns
+/// them once their respawn timer runs out
+pub fn crumble_platforms(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut platforms: Query<(Entity, &mut CrumblingPlatform, &mut Visibility)>,
+    player: Query<(&GroundContacts, Option<&KinematicController>), With<Player>>,
+) {
+    for (entity, mut platform, mut visibility) in platforms.iter_mut() {
+        match platform.state {
+            CrumbleState::Solid => {
+                let landed_on = player.iter().any(|(ground_contacts, controller)| {
+                    is_rider_on(ground_contacts, controller, entity)
+                });
+
+                if landed_on {
+                    platform.crumble_timer.reset();
+                    platform.state = CrumbleState::Crumbling;
+                }
+            }
+            CrumbleState::Crumbling => {
+                platform.crumble_timer.tick(time.delta());
+
+                if platform.crumble_timer.just_finished() {
+                    commands.entity(entity).remove::<Collider>();
+                    *visibility = Visibility::Hidden;
+                    platform.respawn_timer.reset();
+                    platform.state = CrumbleState::Broken;
+                }
+            }
+            CrumbleState::Broken => {
+                platform.respawn_timer.tick(time.delta());
+
+                if platform.respawn_timer.just_finished() {
+                    commands
+                        .entity(entity)
+                        .insert(Collider::cuboid(platform.size.x, platform.size.y));
+                    *visibility = Visibility::Inherited;
+                    platform.state = CrumbleState::Solid;
+                }
+            }
+        }
+    }
+}
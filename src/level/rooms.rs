@@ -0,0 +1,144 @@
+use super::PlayedLdtk;
+use crate::{
+    enemy::{clock::Clock, Enemy},
+    player::Player,
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{ldtk::Level, prelude::*};
+
+/// How the camera moves to a new room
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoomCamera {
+    /// Jump to the player right away
+    Snap,
+    /// Keep following the player smoothly
+    #[default]
+    Pan,
+}
+
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct RoomTransitionSettings {
+    pub camera: RoomCamera,
+}
+
+/// The ldtk level the player is in. The level selection follows it, so the rooms
+/// around it are loaded as neighbours.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct CurrentRoom {
+    pub iid: Option<String>,
+}
+
+/// The player moved from one room to another
+#[derive(Event, Clone, Debug)]
+pub struct RoomChanged {
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// The bounds of a level in world space, as placed by `UseWorldTranslation`. Ldtk's y
+/// axis points down, bevy's up.
+pub fn level_bounds(level: &Level) -> Rect {
+    Rect::new(
+        level.world_x as f32,
+        -(level.world_y + level.px_hei) as f32,
+        (level.world_x + level.px_wid) as f32,
+        -level.world_y as f32,
+    )
+}
+
+/// The bounds of the room the player is in, see `level_bounds`
+pub fn current_room_bounds(ldtk: &LdtkAsset, current_room: &CurrentRoom) -> Option<Rect> {
+    let room = current_room.iid.as_ref()?;
+
+    ldtk.iter_levels()
+        .find(|level| &level.iid == room)
+        .map(level_bounds)
+}
+
+pub fn reset_current_room(mut current_room: ResMut<CurrentRoom>) {
+    current_room.iid = None;
+}
+
+/// Selects the level the player is in, which loads its neighbours and unloads the
+/// rooms that are no longer next to it
+pub fn follow_player_between_rooms(
+    played_ldtk: Res<PlayedLdtk>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    players: Query<(&GlobalTransform, &Player)>,
+    mut current_room: ResMut<CurrentRoom>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut room_changed: EventWriter<RoomChanged>,
+) {
+    // in co-op the rooms follow the first player
+    let first_player = players
+        .iter()
+        .find(|(_, player)| player.index == 0)
+        .map(|(transform, _)| transform);
+
+    let (Some(ldtk), Some(player)) = (ldtk_assets.get(&played_ldtk.0), first_player) else {
+        return;
+    };
+
+    let position = player.translation().truncate();
+
+    let Some(level) = ldtk
+        .iter_levels()
+        .find(|level| level_bounds(level).contains(position))
+    else {
+        return;
+    };
+
+    if current_room.iid.as_ref() == Some(&level.iid) {
+        return;
+    }
+
+    // the first room is the one the level was loaded with, nothing changes
+    if let Some(from) = current_room.iid.replace(level.iid.clone()) {
+        *level_selection = LevelSelection::Iid(level.iid.clone());
+        room_changed.send(RoomChanged {
+            from: Some(from),
+            to: level.iid.clone(),
+        });
+    }
+}
+
+/// Level entities are despawned along with their room by bevy_ecs_ldtk. This despawns
+/// the enemies and projectiles that were spawned during play and are left behind in
+/// rooms that are no longer loaded.
+pub fn despawn_outside_loaded_rooms(
+    mut commands: Commands,
+    mut room_changed: EventReader<RoomChanged>,
+    played_ldtk: Res<PlayedLdtk>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    orphans: Query<(Entity, &GlobalTransform), (Or<(With<Enemy>, With<Clock>)>, Without<Parent>)>,
+) {
+    let Some(ldtk) = ldtk_assets.get(&played_ldtk.0) else {
+        return;
+    };
+
+    for event in room_changed.iter() {
+        let Some(room) = ldtk.iter_levels().find(|level| level.iid == event.to) else {
+            continue;
+        };
+
+        let loaded = ldtk
+            .iter_levels()
+            .filter(|level| {
+                level.iid == room.iid
+                    || room
+                        .neighbours
+                        .iter()
+                        .any(|neighbour| neighbour.level_iid == level.iid)
+            })
+            .map(level_bounds)
+            .collect::<Vec<_>>();
+
+        for (entity, transform) in orphans.iter() {
+            let position = transform.translation().truncate();
+
+            if !loaded.iter().any(|bounds| bounds.contains(position)) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
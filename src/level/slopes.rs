@@ -0,0 +1,106 @@
+use super::{Ground, TILE_SIZE};
+use crate::physics::InitSpriteRigidBody;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::{Collider, Friction};
+
+/// The IntGrid values used for slope tiles in the ldtk level
+pub const SLOPE_RIGHT_45: i32 = 4;
+pub const SLOPE_LEFT_45: i32 = 5;
+pub const SLOPE_RIGHT_SHALLOW_LOW: i32 = 6;
+pub const SLOPE_RIGHT_SHALLOW_HIGH: i32 = 7;
+pub const SLOPE_LEFT_SHALLOW_LOW: i32 = 8;
+pub const SLOPE_LEFT_SHALLOW_HIGH: i32 = 9;
+
+/// The shape of a slope tile. "Right" slopes rise towards the right, "Left" slopes
+/// rise towards the left. The shallow (22.5°) slopes span two tiles, a low and a
+/// high half, with a rise of half a tile each.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Slope {
+    #[default]
+    Right45,
+    Left45,
+    RightShallowLow,
+    RightShallowHigh,
+    LeftShallowLow,
+    LeftShallowHigh,
+}
+
+impl Slope {
+    pub fn from_int_grid_value(value: i32) -> Option<Self> {
+        match value {
+            SLOPE_RIGHT_45 => Some(Slope::Right45),
+            SLOPE_LEFT_45 => Some(Slope::Left45),
+            SLOPE_RIGHT_SHALLOW_LOW => Some(Slope::RightShallowLow),
+            SLOPE_RIGHT_SHALLOW_HIGH => Some(Slope::RightShallowHigh),
+            SLOPE_LEFT_SHALLOW_LOW => Some(Slope::LeftShallowLow),
+            SLOPE_LEFT_SHALLOW_HIGH => Some(Slope::LeftShallowHigh),
+            _ => None,
+        }
+    }
+
+    /// The outline of the solid part of the tile, relative to the tile center
+    pub fn points(&self) -> Vec<Vec2> {
+        let h = TILE_SIZE / 2.0;
+
+        // the right facing shapes, the left facing ones are mirrored
+        let points = match self {
+            Slope::Right45 | Slope::Left45 => {
+                vec![Vec2::new(-h, -h), Vec2::new(h, -h), Vec2::new(h, h)]
+            }
+            Slope::RightShallowLow | Slope::LeftShallowLow => {
+                vec![Vec2::new(-h, -h), Vec2::new(h, -h), Vec2::new(h, 0.0)]
+            }
+            Slope::RightShallowHigh | Slope::LeftShallowHigh => vec![
+                Vec2::new(-h, -h),
+                Vec2::new(h, -h),
+                Vec2::new(h, h),
+                Vec2::new(-h, 0.0),
+            ],
+        };
+
+        match self {
+            Slope::Left45 | Slope::LeftShallowLow | Slope::LeftShallowHigh => points
+                .into_iter()
+                .map(|point| Vec2::new(-point.x, point.y))
+                .collect(),
+            _ => points,
+        }
+    }
+
+    pub fn collider(&self) -> Collider {
+        Collider::convex_hull(self.points()).expect("slope outline to be a valid convex hull")
+    }
+}
+
+#[derive(Clone, Debug, Bundle)]
+pub struct SlopeColliderBundle {
+    pub name: Name,
+    pub slope: Slope,
+    pub collider: Collider,
+    pub rigid_body: InitSpriteRigidBody,
+    pub friction: Friction,
+}
+
+impl From<IntGridCell> for SlopeColliderBundle {
+    fn from(int_grid_cell: IntGridCell) -> Self {
+        let slope = Slope::from_int_grid_value(int_grid_cell.value).unwrap_or_default();
+
+        SlopeColliderBundle {
+            name: Name::new(format!("Slope {:?}", slope)),
+            slope,
+            collider: slope.collider(),
+            rigid_body: InitSpriteRigidBody::Static,
+            friction: Friction::new(0.1),
+        }
+    }
+}
+
+/// Slopes are ground as far as grounding and jumping is concerned, but they keep
+/// their own collider shape and are never merged with the rectangular ground tiles.
+#[derive(Clone, Debug, Bundle, LdtkIntCell)]
+pub struct SlopeBundle {
+    pub ground: Ground,
+    #[from_int_grid_cell]
+    pub slope_collider_bundle: SlopeColliderBundle,
+}
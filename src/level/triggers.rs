@@ -0,0 +1,276 @@
+use super::fields;
+use crate::{
+    physics::InitSpriteRigidBody, player::Player, sprite_colliders::authored_collider, GameState,
+};
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// How long a dialogue line started by a trigger stays on screen
+const DIALOGUE_SECONDS: f32 = 4.0;
+
+/// What a trigger zone does when the player enters it
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriggerAction {
+    StartDialogue(String),
+    SpawnEnemies(u32),
+    ChangeMusic(String),
+    ActivateCheckpoint,
+    EndLevel,
+    CameraChange { zoom: f32 },
+}
+
+impl TriggerAction {
+    /// Reads the action from the `action` field of the entity, using the `text`,
+    /// `count` and `zoom` fields for its parameters
+    pub fn from_entity_instance(entity_instance: &EntityInstance) -> Option<Self> {
+        let text = fields::string_field(entity_instance, "text").unwrap_or_default();
+
+        match fields::string_field(entity_instance, "action")?.as_str() {
+            "StartDialogue" => Some(TriggerAction::StartDialogue(text)),
+            "SpawnEnemies" => Some(TriggerAction::SpawnEnemies(
+                fields::int_field(entity_instance, "count")
+                    .unwrap_or(1)
+                    .max(0) as u32,
+            )),
+            "ChangeMusic" => Some(TriggerAction::ChangeMusic(text)),
+            "ActivateCheckpoint" => Some(TriggerAction::ActivateCheckpoint),
+            "EndLevel" => Some(TriggerAction::EndLevel),
+            "CameraChange" => Some(TriggerAction::CameraChange {
+                zoom: fields::float_field(entity_instance, "zoom").unwrap_or(1.0),
+            }),
+            action => {
+                warn!("unknown trigger action {:?}", action);
+                None
+            }
+        }
+    }
+}
+
+/// Whether a trigger zone fires every time it is entered or only the first time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerMode {
+    #[default]
+    Once,
+    Repeating,
+}
+
+/// A sensor region that fires events when the player enters, stays in or exits it.
+/// Created from the `TriggerZone` ldtk entity, whose size is the size of the region.
+/// Besides the fields read by `TriggerAction`, the Bool field `repeat` selects the
+/// `TriggerMode`.
+#[derive(Component, Clone, Debug, Default)]
+pub struct TriggerZone {
+    pub action: Option<TriggerAction>,
+    pub mode: TriggerMode,
+    pub fired: bool,
+    pub occupants: HashSet<Entity>,
+}
+
+impl From<&EntityInstance> for TriggerZone {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        TriggerZone {
+            action: TriggerAction::from_entity_instance(entity_instance),
+            mode: match fields::bool_field(entity_instance, "repeat") {
+                Some(true) => TriggerMode::Repeating,
+                _ => TriggerMode::Once,
+            },
+            ..default()
+        }
+    }
+}
+
+/// The polygon of the `collider` field, or else the rectangle of the entity
+fn sensor_from_entity_instance(entity_instance: &EntityInstance) -> Collider {
+    authored_collider(entity_instance).unwrap_or_else(|| {
+        Collider::cuboid(entity_instance.width as f32, entity_instance.height as f32)
+    })
+}
+
+#[derive(Bundle, LdtkEntity)]
+pub struct TriggerZoneBundle {
+    #[from_entity_instance]
+    trigger_zone: TriggerZone,
+    #[with(sensor_from_entity_instance)]
+    collider: Collider,
+    sensor: Sensor,
+    rigid_body: InitSpriteRigidBody,
+}
+
+fn level_exit(_: &EntityInstance) -> TriggerZone {
+    TriggerZone {
+        action: Some(TriggerAction::EndLevel),
+        ..default()
+    }
+}
+
+/// The `LevelExit` ldtk entity, a trigger zone that completes the level and continues
+/// the campaign
+#[derive(Bundle, LdtkEntity)]
+pub struct LevelExitBundle {
+    #[with(level_exit)]
+    trigger_zone: TriggerZone,
+    #[with(sensor_from_entity_instance)]
+    collider: Collider,
+    sensor: Sensor,
+    rigid_body: InitSpriteRigidBody,
+}
+
+/// The player entered a trigger zone. `action` is only set if the trigger should act,
+/// i.e. it is repeating or fires for the first time.
+#[derive(Event, Clone, Debug)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub entity: Entity,
+    pub action: Option<TriggerAction>,
+}
+
+/// The player is still inside a trigger zone. Sent every tick.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TriggerStay {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+/// The player left a trigger zone
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub entity: Entity,
+}
+
+/// The last checkpoint the player activated
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct Checkpoint {
+    pub position: Option<Vec2>,
+}
+
+/// A line of dialogue on screen, removed when the timer runs out
+#[derive(Component)]
+pub struct DialogueBox {
+    pub timer: Timer,
+}
+
+/// Tracks the player entering and leaving trigger zones, and sends the trigger events
+pub fn update_trigger_zones(
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>,
+    mut zones: Query<(Entity, &mut TriggerZone)>,
+    players: Query<(), With<Player>>,
+    mut entered: EventWriter<TriggerEntered>,
+    mut stay: EventWriter<TriggerStay>,
+    mut exited: EventWriter<TriggerExited>,
+) {
+    for CollisionStarted(a, b) in started.iter() {
+        for (zone_entity, other) in [(*a, *b), (*b, *a)] {
+            let Ok((_, mut zone)) = zones.get_mut(zone_entity) else {
+                continue;
+            };
+
+            if !players.contains(other) || !zone.occupants.insert(other) {
+                continue;
+            }
+
+            let acts = zone.mode == TriggerMode::Repeating || !zone.fired;
+            zone.fired = true;
+
+            entered.send(TriggerEntered {
+                trigger: zone_entity,
+                entity: other,
+                action: if acts { zone.action.clone() } else { None },
+            });
+        }
+    }
+
+    for CollisionEnded(a, b) in ended.iter() {
+        for (zone_entity, other) in [(*a, *b), (*b, *a)] {
+            if let Ok((_, mut zone)) = zones.get_mut(zone_entity) {
+                if zone.occupants.remove(&other) {
+                    exited.send(TriggerExited {
+                        trigger: zone_entity,
+                        entity: other,
+                    });
+                }
+            }
+        }
+    }
+
+    for (zone_entity, zone) in zones.iter() {
+        for occupant in zone.occupants.iter() {
+            stay.send(TriggerStay {
+                trigger: zone_entity,
+                entity: *occupant,
+            });
+        }
+    }
+}
+
+/// Runs the trigger actions that belong to the level: dialogue, checkpoints and ending
+/// the level. The other actions are handled by the plugins they concern.
+pub fn run_level_trigger_actions(
+    mut commands: Commands,
+    mut triggers: EventReader<TriggerEntered>,
+    zones: Query<&GlobalTransform, With<TriggerZone>>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for event in triggers.iter() {
+        match &event.action {
+            Some(TriggerAction::StartDialogue(text)) => spawn_dialogue(&mut commands, text),
+            Some(TriggerAction::ActivateCheckpoint) => {
+                if let Ok(transform) = zones.get(event.trigger) {
+                    checkpoint.position = Some(transform.translation().truncate());
+                }
+            }
+            Some(TriggerAction::EndLevel) => state.set(GameState::LevelComplete),
+            _ => (),
+        }
+    }
+}
+
+fn spawn_dialogue(commands: &mut Commands, text: &str) {
+    commands.spawn((
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 32.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.0),
+            left: Val::Px(40.0),
+            right: Val::Px(40.0),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.6)),
+        DialogueBox {
+            timer: Timer::from_seconds(DIALOGUE_SECONDS, TimerMode::Once),
+        },
+        Name::new("dialogue"),
+    ));
+}
+
+pub fn update_dialogue(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dialogues: Query<(Entity, &mut DialogueBox)>,
+) {
+    for (entity, mut dialogue) in dialogues.iter_mut() {
+        dialogue.timer.tick(time.delta());
+
+        if dialogue.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn cleanup(mut commands: Commands, dialogues: Query<Entity, With<DialogueBox>>) {
+    for entity in dialogues.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands.insert_resource(Checkpoint::default());
+}
@@ -0,0 +1,314 @@
+//! Checks the ldtk levels without starting the game, see `validate_levels`. Problems
+//! that keep a level from loading or being played are errors, the rest are warnings.
+
+use super::{campaign::Campaign, GROUND, INT_GRID_VALUES, WALL};
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ecs_ldtk::ldtk::{
+    Definitions, EntityInstance, FieldValue, LayerInstance, LdtkJson, Level, Type,
+};
+use serde::Serialize;
+
+/// Where the level and the campaign are read from, relative to the working directory
+const LEVEL_PATH: &str = "assets/sprites/level.ldtk";
+const CAMPAIGN_PATH: &str = "assets/config/main.campaign.ron";
+
+/// The IntGrid values that have a solid collider, nothing should spawn inside them
+const SOLID_INT_GRID_VALUES: [i32; 2] = [GROUND, WALL];
+
+/// The entities that start inside the level and fall out of anything they spawn in
+const SPAWNED_ENTITIES: [&str; 2] = ["Player", "Enemy"];
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub file: String,
+    pub levels: usize,
+    pub errors: Vec<Problem>,
+    pub warnings: Vec<Problem>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Problem {
+    /// The identifier of the level
+    pub level: String,
+    /// The iid of the entity instance, if the problem is about one
+    pub entity: Option<String>,
+    pub message: String,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    fn error(&mut self, level: &Level, entity: Option<&EntityInstance>, message: String) {
+        self.errors.push(Problem::new(level, entity, message));
+    }
+
+    fn warning(&mut self, level: &Level, entity: Option<&EntityInstance>, message: String) {
+        self.warnings.push(Problem::new(level, entity, message));
+    }
+}
+
+impl Problem {
+    fn new(level: &Level, entity: Option<&EntityInstance>, message: String) -> Self {
+        Problem {
+            level: level.identifier.clone(),
+            entity: entity.map(|entity| entity.iid.clone()),
+            message,
+        }
+    }
+}
+
+/// Validates the ldtk file at `path`, or the game's level, and prints a json report.
+/// Returns the exit code: 0 if there are no errors, 1 if there are and 2 if the files
+/// can't be read.
+pub fn validate_levels(path: Option<String>) -> i32 {
+    let path = path.unwrap_or_else(|| LEVEL_PATH.to_string());
+
+    let ldtk = match std::fs::read_to_string(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|json| serde_json::from_str::<LdtkJson>(&json).map_err(|error| error.to_string()))
+    {
+        Ok(ldtk) => ldtk,
+        Err(error) => {
+            eprintln!("could not read {}: {}", path, error);
+            return 2;
+        }
+    };
+
+    let campaign = match std::fs::read_to_string(CAMPAIGN_PATH)
+        .map_err(|error| error.to_string())
+        .and_then(|ron| ron::from_str::<Campaign>(&ron).map_err(|error| error.to_string()))
+    {
+        Ok(campaign) => campaign,
+        Err(error) => {
+            eprintln!(
+                "could not read {}, using the built in campaign: {}",
+                CAMPAIGN_PATH, error
+            );
+            Campaign::default()
+        }
+    };
+
+    let mut report = validate(&ldtk, &campaign);
+    report.file = path;
+
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{}", json),
+        Err(error) => {
+            eprintln!("could not write the report: {}", error);
+            return 2;
+        }
+    }
+
+    if report.is_ok() {
+        0
+    } else {
+        1
+    }
+}
+
+pub fn validate(ldtk: &LdtkJson, campaign: &Campaign) -> ValidationReport {
+    let levels = ldtk
+        .levels
+        .iter()
+        .chain(ldtk.worlds.iter().flat_map(|world| world.levels.iter()))
+        .collect::<Vec<_>>();
+
+    let mut report = ValidationReport {
+        levels: levels.len(),
+        ..default()
+    };
+
+    let identifiers = levels
+        .iter()
+        .map(|level| level.identifier.as_str())
+        .collect::<HashSet<_>>();
+    let iids = levels
+        .iter()
+        .map(|level| level.iid.as_str())
+        .collect::<HashSet<_>>();
+
+    // the campaign continues with the next level at every exit
+    for identifier in campaign
+        .levels
+        .iter()
+        .filter(|identifier| !identifiers.contains(identifier.as_str()))
+    {
+        report.errors.push(Problem {
+            level: identifier.clone(),
+            entity: None,
+            message: "campaign level does not exist".to_string(),
+        });
+    }
+
+    for level in levels {
+        for neighbour in level
+            .neighbours
+            .iter()
+            .filter(|neighbour| !iids.contains(neighbour.level_iid.as_str()))
+        {
+            report.error(
+                level,
+                None,
+                format!("neighbour {} does not exist", neighbour.level_iid),
+            );
+        }
+
+        let Some(layers) = &level.layer_instances else {
+            report.error(level, None, "external levels are not supported".to_string());
+            continue;
+        };
+
+        let entities = layers
+            .iter()
+            .flat_map(|layer| layer.entity_instances.iter())
+            .collect::<Vec<_>>();
+
+        // campaign levels spawn the player, other levels are rooms loaded next to them
+        let players = entities
+            .iter()
+            .filter(|entity| entity.identifier == "Player")
+            .count();
+        if campaign.levels.contains(&level.identifier) {
+            if players != 1 {
+                report.error(
+                    level,
+                    None,
+                    format!("expected exactly one Player, found {}", players),
+                );
+            }
+
+            if !entities
+                .iter()
+                .any(|entity| entity.identifier == "LevelExit")
+            {
+                report.warning(
+                    level,
+                    None,
+                    "no LevelExit, the level can't be completed".to_string(),
+                );
+            }
+        } else if players > 0 {
+            report.error(
+                level,
+                None,
+                format!("rooms can't have a Player, found {}", players),
+            );
+        }
+
+        for entity in entities.iter() {
+            check_field_ranges(&mut report, &ldtk.defs, level, entity);
+        }
+
+        for layer in layers
+            .iter()
+            .filter(|layer| matches!(layer.layer_instance_type, Type::IntGrid))
+        {
+            let unknown = layer
+                .int_grid_csv
+                .iter()
+                .filter(|value| **value != 0 && !INT_GRID_VALUES.contains(value))
+                .collect::<HashSet<_>>();
+            for value in unknown {
+                report.error(
+                    level,
+                    None,
+                    format!(
+                        "IntGrid value {} in layer {} is unknown",
+                        value, layer.identifier
+                    ),
+                );
+            }
+
+            for entity in entities
+                .iter()
+                .filter(|entity| SPAWNED_ENTITIES.contains(&entity.identifier.as_str()))
+            {
+                if let Some(value) = solid_value_at(layer, entity_center(entity)) {
+                    report.error(
+                        level,
+                        Some(entity),
+                        format!(
+                            "{} spawns inside IntGrid value {} at {:?}",
+                            entity.identifier, value, entity.px
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    report
+}
+
+/// Checks the number fields of an entity against the min and max of their definition
+fn check_field_ranges(
+    report: &mut ValidationReport,
+    defs: &Definitions,
+    level: &Level,
+    entity: &EntityInstance,
+) {
+    let Some(entity_def) = defs
+        .entities
+        .iter()
+        .find(|entity_def| entity_def.uid == entity.def_uid)
+    else {
+        report.error(
+            level,
+            Some(entity),
+            format!("{} has no definition", entity.identifier),
+        );
+        return;
+    };
+
+    for field in entity.field_instances.iter() {
+        let value = match field.value {
+            FieldValue::Int(Some(value)) => value as f32,
+            FieldValue::Float(Some(value)) => value,
+            _ => continue,
+        };
+
+        let Some(field_def) = entity_def
+            .field_defs
+            .iter()
+            .find(|field_def| field_def.uid == field.def_uid)
+        else {
+            continue;
+        };
+
+        let too_small = field_def.min.map_or(false, |min| value < min);
+        let too_large = field_def.max.map_or(false, |max| value > max);
+        if too_small || too_large {
+            report.error(
+                level,
+                Some(entity),
+                format!(
+                    "{}.{} is {}, outside of {:?}..={:?}",
+                    entity.identifier, field.identifier, value, field_def.min, field_def.max
+                ),
+            );
+        }
+    }
+}
+
+/// The center of an entity in level pixels, ldtk's y axis points down
+fn entity_center(entity: &EntityInstance) -> Vec2 {
+    entity.px.as_vec2()
+        + (Vec2::splat(0.5) - entity.pivot) * Vec2::new(entity.width as f32, entity.height as f32)
+}
+
+/// The solid IntGrid value of the cell at `position`, if there is one
+fn solid_value_at(layer: &LayerInstance, position: Vec2) -> Option<i32> {
+    let cell = (position / layer.grid_size as f32).floor().as_ivec2();
+
+    if cell.x < 0 || cell.y < 0 || cell.x >= layer.c_wid || cell.y >= layer.c_hei {
+        return None;
+    }
+
+    layer
+        .int_grid_csv
+        .get((cell.y * layer.c_wid + cell.x) as usize)
+        .copied()
+        .filter(|value| SOLID_INT_GRID_VALUES.contains(value))
+}
@@ -8,31 +8,46 @@ mod audio;
 mod utils;
 mod background;
 mod camera;
+mod character_controller;
+mod collision_matrix;
+mod config;
+mod contacts;
 #[cfg(debug_assertions)]
 mod debug;
 mod enemy;
+mod items;
 mod level;
 mod loading;
 mod menu;
 mod physics;
 mod player;
 mod shader_utils;
+mod sprite_colliders;
+mod timestep;
 mod video;
 
 use crate::actions::ActionsPlugin;
 use crate::audio::InternalAudioPlugin;
 use crate::background::BackgroundPlugin;
 use crate::camera::CameraPlugin;
+use crate::character_controller::CharacterControllerPlugin;
+use crate::collision_matrix::CollisionMatrixPlugin;
+use crate::contacts::ContactsPlugin;
 #[cfg(debug_assertions)]
 use crate::debug::DebugPlugin;
 use crate::enemy::EnemyPlugin;
+use crate::items::ItemsPlugin;
 use crate::level::LevelPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
 use crate::physics::PhysicsPlugin;
 use crate::player::PlayerPlugin;
+use crate::shader_utils::ShaderEffectsPlugin;
+use crate::timestep::TimestepPlugin;
 use crate::video::VideoPlugin;
 
+pub use crate::level::validation::validate_levels;
+
 pub const GAME_SIZE: Vec2 = Vec2 { x: 1600., y: 900. };
 
 ///This example game uses States to separate logic
@@ -64,16 +79,24 @@ enum GameState {
     /// - `SpawningPlayer` => `InitializingPhysics`
     SpawningEntities,
 
-    /// Initializes physics
+    /// Initializes physics and waits for the bodies to settle
     /// - `SpawningPlayer` => `InitializingPhysics`
-    /// - `InitializingPhysics` => `Playing`
+    /// - `InitializingPhysics` => `Playing` (see `PhysicsSettleSettings`)
     InitializingPhysics,
 
     /// During this State the actual game logic is executed
     /// - `InitializingPhysics` => `Playing`
+    /// - `Playing` => `LevelComplete`
     /// - `Playing` => `PlayingCutScene`
     Playing,
 
+    /// Shows the stats of the completed level
+    /// - `Playing` => `LevelComplete`
+    /// - `LevelComplete` => `LoadingLevel` if there is a next level, or the next stage
+    ///   in endless mode
+    /// - `LevelComplete` => `PlayingCutScene` at the end of the campaign
+    LevelComplete,
+
     /// Here the cutscene is played
     /// - `Playing` => `PlayingCutScene`
     /// - `PlayingCutScene` => `Menu`
@@ -104,17 +127,26 @@ impl Plugin for GamePlugin {
                     }),
                     ..default()
                 })
-                .set(ImagePlugin::default_nearest()),
+                .set(ImagePlugin::default_nearest())
+                .set(asset_plugin()),
             LoadingPlugin,
             MenuPlugin,
             ActionsPlugin,
             InternalAudioPlugin,
             PlayerPlugin,
             VideoPlugin,
-            PhysicsPlugin,
+            (
+                TimestepPlugin,
+                CollisionMatrixPlugin,
+                PhysicsPlugin,
+                CharacterControllerPlugin,
+                ContactsPlugin,
+            ),
             LevelPlugin,
+            ShaderEffectsPlugin,
             BackgroundPlugin,
             EnemyPlugin,
+            ItemsPlugin,
             CameraPlugin,
         ));
 
@@ -124,3 +156,15 @@ impl Plugin for GamePlugin {
         }
     }
 }
+
+/// Debug builds with the `dev` feature reload assets that change on disk, like the ldtk
+/// level. Watching the files needs bevy's `filesystem_watcher`, which only `dev` enables.
+fn asset_plugin() -> AssetPlugin {
+    AssetPlugin {
+        #[cfg(all(debug_assertions, feature = "dev"))]
+        watch_for_changes: bevy::asset::ChangeWatcher::with_delay(
+            bevy::utils::Duration::from_millis(200),
+        ),
+        ..default()
+    }
+}
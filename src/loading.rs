@@ -1,4 +1,4 @@
-use crate::GameState;
+use crate::{collision_matrix::CollisionMatrix, level::campaign::Campaign, GameState};
 use bevy::prelude::*;
 use bevy_asset_loader::asset_collection::AssetCollection;
 use bevy_asset_loader::prelude::*;
@@ -16,6 +16,7 @@ impl Plugin for LoadingPlugin {
             LoadingState::new(GameState::Loading).continue_to_state(GameState::Menu),
         )
         .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, ConfigAssets>(GameState::Loading)
         .add_collection_to_loading_state::<_, PlayerWalkTextureAtlasAsset>(GameState::Loading)
         .add_collection_to_loading_state::<_, EnemyTextureAtlasAsset>(GameState::Loading)
         .add_collection_to_loading_state::<_, ClockTextureAtlasAsset>(GameState::Loading)
@@ -31,6 +32,9 @@ pub struct AudioAssets {
     #[asset(path = "audio/main_loop.ogg")]
     pub main_music_loop: Handle<AudioSource>,
 
+    #[asset(path = "audio/fuzzy_loop.ogg")]
+    pub fuzzy_music_loop: Handle<AudioSource>,
+
     #[asset(path = "audio/alarm_ring.ogg")]
     pub alarm: Handle<AudioSource>,
 
@@ -38,6 +42,15 @@ pub struct AudioAssets {
     pub tick_tock: Handle<AudioSource>,
 }
 
+#[derive(AssetCollection, Resource)]
+pub struct ConfigAssets {
+    #[asset(path = "config/layers.collision.ron")]
+    pub collision_matrix: Handle<CollisionMatrix>,
+
+    #[asset(path = "config/main.campaign.ron")]
+    pub campaign: Handle<Campaign>,
+}
+
 #[derive(AssetCollection, Resource)]
 pub struct PlayerWalkTextureAtlasAsset {
     #[asset(texture_atlas(tile_size_x = 32., tile_size_y = 32., columns = 3, rows = 1))]
@@ -9,6 +9,12 @@ use std::io::Cursor;
 use winit::window::Icon;
 
 fn main() {
+    // `hook_the_game validate-levels [path]` checks the ldtk levels without a window
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("validate-levels") {
+        std::process::exit(hook_the_game::validate_levels(args.next()));
+    }
+
     App::new()
         .insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::rgb_u8(0, 160, 255)))
@@ -1,15 +1,22 @@
-use crate::GameState;
+use crate::{
+    level::{campaign::SaveData, endless::GameMode},
+    player::LocalPlayers,
+    GameState,
+};
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
+/// This plugin is responsible for the game menu (containing two buttons...)
 /// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
             .add_systems(OnEnter(GameState::Menu), setup_menu)
-            .add_systems(Update, click_play_button.run_if(in_state(GameState::Menu)))
+            .add_systems(
+                Update,
+                (click_menu_buttons, click_setting_buttons).run_if(in_state(GameState::Menu)),
+            )
             .add_systems(OnExit(GameState::Menu), cleanup_menu);
     }
 }
@@ -29,61 +36,194 @@ impl Default for ButtonColors {
     }
 }
 
-fn setup_menu(mut commands: Commands, button_colors: Res<ButtonColors>) {
+/// The root node of the menu
+#[derive(Component)]
+struct Menu;
+
+/// A button that starts the game in a mode, for one or two local players
+#[derive(Component)]
+struct MenuButton {
+    mode: GameMode,
+    players: usize,
+    label: &'static str,
+}
+
+/// A button that changes a setting of the save data
+#[derive(Component, Clone, Copy)]
+enum SettingButton {
+    /// The accessibility setting that turns the camera effects off
+    ReduceMotion,
+    ResolutionMode,
+}
+
+impl SettingButton {
+    fn label(self, save_data: &SaveData) -> String {
+        match self {
+            SettingButton::ReduceMotion if save_data.reduce_motion => "Shake: Off".to_string(),
+            SettingButton::ReduceMotion => "Shake: On".to_string(),
+            SettingButton::ResolutionMode => save_data.resolution_mode.label().to_string(),
+        }
+    }
+
+    fn toggle(self, save_data: &mut SaveData) {
+        match self {
+            SettingButton::ReduceMotion => save_data.reduce_motion = !save_data.reduce_motion,
+            SettingButton::ResolutionMode => {
+                save_data.resolution_mode = save_data.resolution_mode.next()
+            }
+        }
+    }
+}
+
+fn setup_menu(mut commands: Commands, button_colors: Res<ButtonColors>, save_data: Res<SaveData>) {
     commands
         .spawn((
-            ButtonBundle {
+            NodeBundle {
                 style: Style {
-                    width: Val::Px(120.0),
-                    height: Val::Px(50.0),
                     margin: UiRect::all(Val::Auto),
-                    justify_content: JustifyContent::Center,
-                    align_items: AlignItems::Center,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(10.0),
                     ..default()
                 },
-                background_color: button_colors.normal.into(),
-                ..Default::default()
+                ..default()
             },
-            Name::new("button"),
+            Menu,
+            Name::new("menu"),
         ))
         .with_children(|parent| {
-            parent.spawn(TextBundle::from_section(
-                "Play",
-                TextStyle {
-                    font_size: 40.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
-                    ..default()
-                },
-            ));
+            for (mode, players, label) in [
+                (GameMode::Campaign, 1, "Play"),
+                (GameMode::Campaign, 2, "Co-op"),
+                (GameMode::Endless, 1, "Endless"),
+            ] {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(180.0),
+                                height: Val::Px(50.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..Default::default()
+                        },
+                        MenuButton {
+                            mode,
+                            players,
+                            label,
+                        },
+                        Name::new("button"),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+
+            for setting in [SettingButton::ReduceMotion, SettingButton::ResolutionMode] {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(180.0),
+                                height: Val::Px(40.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: button_colors.normal.into(),
+                            ..Default::default()
+                        },
+                        setting,
+                        Name::new("setting"),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            setting.label(&save_data),
+                            TextStyle {
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
         });
 }
 
-fn click_play_button(
+/// Changes the setting and stores it right away
+fn click_setting_buttons(
+    button_colors: Res<ButtonColors>,
+    mut save_data: ResMut<SaveData>,
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &mut BackgroundColor,
+            &SettingButton,
+            &Children,
+        ),
+        Changed<Interaction>,
+    >,
+    mut text: Query<&mut Text, With<Parent>>,
+) {
+    for (interaction, mut color, setting, children) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                setting.toggle(&mut save_data);
+                save_data.store();
+
+                if let Ok(mut text) = text.get_mut(children[0]) {
+                    text.sections[0].value = setting.label(&save_data);
+                }
+            }
+            Interaction::Hovered => *color = button_colors.hovered.into(),
+            Interaction::None => *color = button_colors.normal.into(),
+        }
+    }
+}
+
+fn click_menu_buttons(
     button_colors: Res<ButtonColors>,
     mut state: ResMut<NextState<GameState>>,
+    mut mode: ResMut<GameMode>,
+    mut local_players: ResMut<LocalPlayers>,
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>, With<Children>),
+        (&Interaction, &mut BackgroundColor, &MenuButton, &Children),
+        (Changed<Interaction>, With<Button>),
     >,
     mut text: Query<&mut Text, With<Parent>>,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
+    for (interaction, mut color, button, children) in &mut interaction_query {
+        let Ok(mut text) = text.get_mut(children[0]) else {
+            continue;
+        };
+
         match *interaction {
             Interaction::Pressed => {
+                *mode = button.mode;
+                local_players.0 = button.players;
                 state.set(GameState::LoadingLevel);
             }
             Interaction::Hovered => {
                 *color = button_colors.hovered.into();
-                text.single_mut().sections[0].value = "yalP".to_string();
+                text.sections[0].value = button.label.chars().rev().collect();
             }
             Interaction::None => {
                 *color = button_colors.normal.into();
-                text.single_mut().sections[0].value = "Play".to_string();
+                text.sections[0].value = button.label.to_string();
             }
         }
     }
 }
 
-fn cleanup_menu(mut commands: Commands, button: Query<Entity, With<Button>>) {
-    commands.entity(button.single()).despawn_recursive();
+fn cleanup_menu(mut commands: Commands, menu: Query<Entity, With<Menu>>) {
+    commands.entity(menu.single()).despawn_recursive();
 }
@@ -1,18 +1,38 @@
 use crate::{
+    collision_matrix::CollisionMatrix,
+    contacts::GroundContacts,
     enemy::Enemy,
-    level::{Ground, Wall},
+    items::Pickup,
+    level::{
+        hazards::WaterContacts,
+        platforms::{OneWayPlatform, PlatformRider},
+        triggers::TriggerZone,
+        Ground, Wall,
+    },
     player::{self, Player},
+    sprite_colliders::{SpriteColliders, SpriteHull, SpriteHullCache},
+    timestep::{Interpolated, DEFAULT_TICK_RATE},
     GameState,
 };
-use bevy::{prelude::*, render::primitives::Aabb, utils::HashSet};
+use bevy::{
+    prelude::*,
+    render::primitives::Aabb,
+    utils::{Duration, HashSet},
+};
+use bevy_ecs_ldtk::EntityInstance;
 use bevy_xpbd_2d::prelude::*;
+use serde::Deserialize;
 
-pub const NEXT_STATE: GameState = GameState::Playing;
+/// How long to wait for the physics bodies to settle before continuing anyway
+pub const DEFAULT_SETTLE_TIMEOUT_SECONDS: f32 = 5.0;
 
 // character is 32px tall, assume 2m in height, 1m = 16px
 // pub const GRAVITY: f32 = 9.8 * 16.0;
 pub const GRAVITY: f32 = 700.0;
 
+/// Slopes steeper than this (the y component of the surface normal) are not walkable
+pub const MAX_SLOPE_NORMAL_Y: f32 = 0.7;
+
 // #[derive(Reflect, Resource, Default, InspectorOptions)]
 // #[reflect(Resource, InspectorOptions)]
 // pub struct PhysicsConstants {
@@ -31,25 +51,77 @@ pub enum InitSpriteRigidBody {
     Static,
 }
 
-#[derive(PhysicsLayer)]
+/// Overrides the collision layer `init_sprite_physics` picks for a non-living body
+#[derive(Component, Clone, Copy, Debug)]
+pub struct InitCollisionLayer(pub PhysicsLayers);
+
+impl Default for InitCollisionLayer {
+    fn default() -> Self {
+        InitCollisionLayer(PhysicsLayers::Ground)
+    }
+}
+
+/// The collision layers of the game. Which layers interact is defined by the
+/// `CollisionMatrix`.
+#[derive(PhysicsLayer, Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub enum PhysicsLayers {
     Player,
     Enemy,
     Wall,
     Ground,
     Projectile,
+    Platform,
+    Trigger,
+    Pickup,
+    Hazard,
+}
+
+/// Settings for `GameState::InitializingPhysics`, which waits for the dynamic bodies of
+/// the level to fall asleep before the game continues
+#[derive(Resource, Clone, Debug)]
+pub struct PhysicsSettleSettings {
+    /// The state to continue to once the bodies settled, or the timeout ran out
+    pub next_state: GameState,
+    /// The longest time to wait for the bodies to settle
+    pub timeout: Duration,
 }
 
+impl Default for PhysicsSettleSettings {
+    fn default() -> Self {
+        PhysicsSettleSettings {
+            next_state: GameState::Playing,
+            timeout: Duration::from_secs_f32(DEFAULT_SETTLE_TIMEOUT_SECONDS),
+        }
+    }
+}
+
+/// How long the game has been waiting for the physics bodies to settle
+#[derive(Resource, Default, Debug)]
+struct SettleTime(Duration);
+
 pub struct PhysicsPlugin;
 
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
+/// The physics step runs in `FixedUpdate`, once per tick, see `TimestepPlugin`
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(bevy_xpbd_2d::prelude::PhysicsPlugins::default())
+        app.add_plugins(bevy_xpbd_2d::prelude::PhysicsPlugins::new(FixedUpdate))
+            .insert_resource(PhysicsTimestep::FixedOnce(1.0 / DEFAULT_TICK_RATE as f32))
             .insert_resource(Gravity(Vec2::NEG_Y * GRAVITY))
             .register_type::<HashSet<Entity>>()
-            .add_systems(OnEnter(GameState::InitializingPhysics), init_sprite_physics)
+            .init_resource::<PhysicsSettleSettings>()
+            .init_resource::<SpriteHullCache>()
+            .init_resource::<SettleTime>()
+            .add_systems(
+                OnEnter(GameState::InitializingPhysics),
+                (init_sprite_physics, reset_settle_time),
+            )
+            // bodies spawned during play, e.g. enemies spawned by trigger zones
+            .add_systems(
+                Update,
+                init_sprite_physics.run_if(in_state(GameState::Playing)),
+            )
             .add_systems(
                 Update,
                 next_state_after_physics_settle
@@ -69,23 +141,64 @@ pub fn init_sprite_physics(
     non_living: Query<
         (
             Entity,
+            Option<&Collider>,
             Option<&Aabb>,
+            Option<&EntityInstance>,
+            Option<&Handle<TextureAtlas>>,
+            Option<&Handle<Image>>,
             &InitSpriteRigidBody,
             Option<&Ground>,
             Option<&Wall>,
+            Option<&OneWayPlatform>,
+            Option<&TriggerZone>,
+            Option<&Pickup>,
+            Option<&InitCollisionLayer>,
+            Option<&SpriteHull>,
         ),
         (Without<Player>, Without<Enemy>),
     >,
-    player: Query<(Entity, &InitSpriteRigidBody), With<Player>>,
-    enemy: Query<(Entity, &InitSpriteRigidBody), With<Enemy>>,
+    player: Query<
+        (
+            Entity,
+            &InitSpriteRigidBody,
+            Option<&EntityInstance>,
+            Option<&SpriteHull>,
+            Option<&Handle<TextureAtlas>>,
+        ),
+        With<Player>,
+    >,
+    enemy: Query<
+        (
+            Entity,
+            &InitSpriteRigidBody,
+            Option<&EntityInstance>,
+            Option<&SpriteHull>,
+            Option<&Handle<TextureAtlas>>,
+        ),
+        With<Enemy>,
+    >,
+    collision_matrix: Res<CollisionMatrix>,
+    mut sprite_colliders: SpriteColliders,
 ) {
-    console_log!(
-        "init_sprite_physics: non-player sprites: {}",
-        non_living.iter().len()
-    );
+    if !non_living.is_empty() {
+        console_log!(
+            "init_sprite_physics: non-player sprites: {}",
+            non_living.iter().len()
+        );
+    }
+
+    // set up player entities, using the authored collider or the opted in sprite hull if
+    // there is one and the hand-sized PLAYER_COLLISION_SIZE otherwise
+    for (e, srb, entity_instance, sprite_hull, atlas) in player.iter() {
+        let collider = sprite_colliders
+            .collider(entity_instance, sprite_hull, atlas, None)
+            .unwrap_or_else(|| {
+                Collider::cuboid(
+                    player::PLAYER_COLLISION_SIZE.x,
+                    player::PLAYER_COLLISION_SIZE.y,
+                )
+            });
 
-    // set up player entities using the player.size for the collider
-    for (e, srb) in player.iter() {
         commands
             .entity(e)
             .insert((
@@ -94,23 +207,8 @@ pub fn init_sprite_physics(
                     InitSpriteRigidBody::Kinematic => RigidBody::Kinematic,
                     InitSpriteRigidBody::Static => RigidBody::Static,
                 },
-                Collider::compound(vec![(
-                    Position::default(),
-                    Rotation::default(),
-                    Collider::cuboid(
-                        player::PLAYER_COLLISION_SIZE.x,
-                        player::PLAYER_COLLISION_SIZE.y,
-                    ),
-                )]),
-                CollisionLayers::new(
-                    [PhysicsLayers::Player],
-                    [
-                        PhysicsLayers::Enemy,
-                        PhysicsLayers::Ground,
-                        PhysicsLayers::Wall,
-                        PhysicsLayers::Projectile,
-                    ],
-                ),
+                collider,
+                collision_matrix.collision_layers(PhysicsLayers::Player),
                 LockedAxes::ROTATION_LOCKED,
                 Restitution::ZERO.with_combine_rule(CoefficientCombine::Min),
                 ExternalForce::ZERO,
@@ -120,12 +218,26 @@ pub fn init_sprite_physics(
                     combine_rule: CoefficientCombine::Average,
                 },
                 RayCaster::new(Vec2::ZERO, Vec2::NEG_Y),
+                PlatformRider::default(),
+                Interpolated::default(),
+                GroundContacts::default(),
+                WaterContacts::default(),
             ))
             .remove::<InitSpriteRigidBody>();
     }
 
     // set up enemy entities
-    for (e, srb) in enemy.iter() {
+    for (e, srb, entity_instance, sprite_hull, atlas) in enemy.iter() {
+        let collider = sprite_colliders
+            .collider(entity_instance, sprite_hull, atlas, None)
+            .unwrap_or_else(|| {
+                Collider::compound(vec![(
+                    Position(Vec2::new(0.0, -4.0)),
+                    Rotation::default(),
+                    Collider::cuboid(64.0, 25.0),
+                )])
+            });
+
         commands
             .entity(e)
             .insert((
@@ -134,19 +246,8 @@ pub fn init_sprite_physics(
                     InitSpriteRigidBody::Kinematic => RigidBody::Kinematic,
                     InitSpriteRigidBody::Static => RigidBody::Static,
                 },
-                Collider::compound(vec![(
-                    Position(Vec2::new(0.0, -4.0)),
-                    Rotation::default(),
-                    Collider::cuboid(64.0, 25.0),
-                )]),
-                CollisionLayers::new(
-                    [PhysicsLayers::Enemy],
-                    [
-                        PhysicsLayers::Player,
-                        PhysicsLayers::Ground,
-                        PhysicsLayers::Wall,
-                    ],
-                ),
+                collider,
+                collision_matrix.collision_layers(PhysicsLayers::Enemy),
                 LockedAxes::ROTATION_LOCKED,
                 Restitution::ZERO.with_combine_rule(CoefficientCombine::Min),
                 ExternalForce::ZERO,
@@ -156,15 +257,42 @@ pub fn init_sprite_physics(
                     combine_rule: CoefficientCombine::Average,
                 },
                 RayCaster::new(Vec2::ZERO, Vec2::NEG_Y),
+                PlatformRider::default(),
+                Interpolated::default(),
+                GroundContacts::default(),
             ))
             .remove::<InitSpriteRigidBody>();
     }
 
-    // set up non-player entities, using the Aabb bounds of the sprite for the collider
-    for (e, aabb, srb, ground, wall) in non_living.iter() {
+    // set up non-player entities, using the authored or generated collider, or the Aabb
+    // bounds of the sprite, unless a collider was already provided (e.g. merged tile
+    // colliders)
+    for (
+        e,
+        existing_collider,
+        aabb,
+        entity_instance,
+        atlas,
+        image,
+        srb,
+        ground,
+        wall,
+        platform,
+        trigger,
+        pickup,
+        collision_layer,
+        sprite_hull,
+    ) in non_living.iter()
+    {
         let collider;
 
-        if let Some(aabb) = aabb {
+        if let Some(existing_collider) = existing_collider {
+            collider = existing_collider.clone();
+        } else if let Some(sprite_collider) =
+            sprite_colliders.collider(entity_instance, sprite_hull, atlas, image)
+        {
+            collider = sprite_collider;
+        } else if let Some(aabb) = aabb {
             collider = collider_from_aabb(aabb);
         } else {
             //if there is no Aabb component, assuming these are 32x32 sprites
@@ -187,31 +315,14 @@ pub fn init_sprite_physics(
         //     Friction::default()
         // };
 
-        let collision_layers = match (ground, wall) {
-            (Some(_), _) => CollisionLayers::new(
-                [PhysicsLayers::Ground],
-                [
-                    PhysicsLayers::Player,
-                    PhysicsLayers::Enemy,
-                    PhysicsLayers::Projectile,
-                ],
-            ),
-            (_, Some(_)) => CollisionLayers::new(
-                [PhysicsLayers::Wall],
-                [
-                    PhysicsLayers::Player,
-                    PhysicsLayers::Enemy,
-                    PhysicsLayers::Projectile,
-                ],
-            ),
-            _ => CollisionLayers::new(
-                [PhysicsLayers::Ground],
-                [
-                    PhysicsLayers::Player,
-                    PhysicsLayers::Enemy,
-                    PhysicsLayers::Projectile,
-                ],
-            ),
+        let layer = match (ground, wall) {
+            _ if collision_layer.is_some() => collision_layer.unwrap().0,
+            _ if trigger.is_some() => PhysicsLayers::Trigger,
+            _ if pickup.is_some() => PhysicsLayers::Pickup,
+            _ if platform.is_some() => PhysicsLayers::Platform,
+            (Some(_), _) => PhysicsLayers::Ground,
+            (_, Some(_)) => PhysicsLayers::Wall,
+            _ => PhysicsLayers::Ground,
         };
 
         commands
@@ -223,49 +334,90 @@ pub fn init_sprite_physics(
                     InitSpriteRigidBody::Static => RigidBody::Static,
                 },
                 collider,
-                collision_layers,
+                collision_matrix.collision_layers(layer),
                 LockedAxes::ROTATION_LOCKED,
                 Restitution::ZERO.with_combine_rule(CoefficientCombine::Min),
                 ExternalForce::ZERO,
             ))
             .remove::<InitSpriteRigidBody>();
-    }
-}
 
-pub fn check_if_grounded(
-    player: &Query<(&RayHits, &CollidingEntities), With<Player>>,
-    grounds: &Query<Entity, With<Ground>>,
-) -> bool {
-    let grounds = grounds.iter().collect::<Vec<Entity>>();
-    //screen_print!("check_if_ground grounds: {:#?}", grounds);
-    let (hits, coll_ents) = player.single();
-    // screen_print!(
-    //     "check_if_ground hits: {:#?}",
-    //     hits.iter().collect::<Vec<&RayHitData>>()
-    // );
-
-    for collision_entity in coll_ents.iter() {
-        if grounds.contains(collision_entity) {
-            // collision with ground. ensure that we are actually above it
-            for hit in hits.iter() {
-                if *collision_entity == hit.entity {
-                    return true;
-                }
-            }
+        // only bodies that move need their sprites interpolated
+        if !matches!(srb, InitSpriteRigidBody::Static) {
+            commands.entity(e).insert(Interpolated::default());
         }
     }
+}
 
-    // otherwise, not grounded
+/// Returns the normal of the closest walkable ground below the ray caster, if it is no
+/// further away than `max_distance`. Used to keep characters snapped to slopes.
+pub fn ground_normal_below(
+    hits: &RayHits,
+    grounds: &Query<Entity, With<Ground>>,
+    max_distance: f32,
+) -> Option<Vec2> {
+    hits.iter()
+        .filter(|hit| grounds.contains(hit.entity) && hit.time_of_impact <= max_distance)
+        .min_by(|a, b| a.time_of_impact.total_cmp(&b.time_of_impact))
+        .map(|hit| hit.normal)
+        .filter(|normal| normal.y >= MAX_SLOPE_NORMAL_Y)
+}
 
-    false
+fn reset_settle_time(mut settle_time: ResMut<SettleTime>) {
+    settle_time.0 = Duration::ZERO;
 }
 
-/// Waits until all RigidBodies are sleeping before transitioning to the next state
-pub fn next_state_after_physics_settle(
+/// Waits until all dynamic bodies are sleeping before transitioning to the next state.
+/// Static and kinematic bodies never fall asleep, so they are not waited for. If the
+/// bodies don't settle within the timeout, the ones that are still awake are reported
+/// and the game continues anyway.
+fn next_state_after_physics_settle(
+    time: Res<Time>,
+    settings: Res<PhysicsSettleSettings>,
+    mut settle_time: ResMut<SettleTime>,
     mut state: ResMut<NextState<GameState>>,
-    not_sleeping_q: Query<Entity, (With<RigidBody>, With<Sleeping>)>,
+    awake: Query<
+        (
+            Entity,
+            &RigidBody,
+            Option<&Name>,
+            Option<&Position>,
+            Option<&LinearVelocity>,
+        ),
+        Without<Sleeping>,
+    >,
 ) {
-    if not_sleeping_q.iter().len() == 0 {
-        state.set(NEXT_STATE);
+    let awake = awake
+        .iter()
+        .filter(|(_, rigid_body, ..)| rigid_body.is_dynamic())
+        .collect::<Vec<_>>();
+
+    if awake.is_empty() {
+        info!("physics settled after {:.2}s", settle_time.0.as_secs_f32());
+        state.set(settings.next_state.clone());
+        return;
     }
+
+    settle_time.0 += time.delta();
+
+    if settle_time.0 < settings.timeout {
+        return;
+    }
+
+    warn!(
+        "physics did not settle within {:.2}s, {} bodies still awake:",
+        settings.timeout.as_secs_f32(),
+        awake.len()
+    );
+
+    for (entity, _, name, position, velocity) in awake {
+        warn!(
+            "  {:?} {:?} at {:?} moving at {:?}",
+            entity,
+            name.map(|name| name.as_str()).unwrap_or("unnamed"),
+            position.map(|position| position.0),
+            velocity.map(|velocity| velocity.0)
+        );
+    }
+
+    state.set(settings.next_state.clone());
 }
@@ -1,13 +1,29 @@
+use crate::camera::effects::{CameraShake, ZoomPunch};
+use crate::character_controller::{CharacterBody, KinematicController};
+use crate::contacts::GroundContacts;
+use crate::items::Inventory;
+use crate::level::fields;
+use crate::level::hazards::{WaterContacts, SINK_SPEED, SWIM_SPEED};
+use crate::level::triggers::Checkpoint;
 use crate::loading::PlayerWalkTextureAtlasAsset;
+use crate::shader_utils::HitFlash;
+use crate::timestep::FixedGameplaySet;
+use crate::timestep::Interpolated;
 use crate::GameState;
 use crate::*;
 use crate::{actions::Actions, level::Ground};
 use bevy_ecs_ldtk::prelude::*;
-use bevy_xpbd_2d::prelude::{CollidingEntities, LinearVelocity, RayHits};
+use bevy_xpbd_2d::prelude::{ColliderAabb, LinearVelocity, Position, RayHits};
 
 pub const PLAYER_COLLISION_SIZE: Vec2 = Vec2 { x: 10.0, y: 32.0 };
 pub const WALK_SPEED: f32 = 150.;
 pub const JUMP_SPEED: f32 = 300.;
+/// How far below the player's feet the ground may be and still be snapped to
+pub const GROUND_SNAP_DISTANCE: f32 = 8.;
+/// How far to the side of the first player the second player spawns
+const PARTNER_SPAWN_OFFSET: Vec2 = Vec2 { x: 24.0, y: 0.0 };
+/// Tints the sprite of each player, so they can tell their characters apart
+const PLAYER_TINTS: [Color; 2] = [Color::WHITE, Color::rgb(0.6, 0.8, 1.0)];
 
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
@@ -15,107 +31,275 @@ impl Plugin for PlayerPlugin {
         app
             // register the Player type to see the details in the egui inspector
             .register_type::<Player>()
+            .init_resource::<LocalPlayers>()
             // register the PlayerLdtkBundle in order to spawn the player entity via
             // the ldtk level
             .register_ldtk_entity::<PlayerLdtkBundle>("Player")
             .add_systems(OnEnter(GameState::SpawningEntities), initialize_player)
             .add_systems(
                 Update,
-                (move_player, update_player_animation, death_check)
-                    .run_if(in_state(GameState::Playing)),
+                update_player_animation.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (move_player, death_check).in_set(FixedGameplaySet),
             )
             .add_systems(OnExit(GameState::Playing), cleanup);
     }
 }
 
+/// How many players play on this machine. Chosen in the menu.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        LocalPlayers(1)
+    }
+}
+
 #[derive(Component, Reflect)]
 pub struct Player {
+    /// 0 for the player spawned by the level, who the rooms follow, 1 for the second
+    /// local player. Rooms aren't loaded around the second player, who can walk out of
+    /// them.
+    pub index: usize,
     pub walk_speed: f32,
     pub jump_speed: f32,
     pub is_jumping: bool,
+    /// Whether the player stood on the ground in the previous tick
+    pub was_grounded: bool,
+    /// How often the player jumped since leaving the ground, see `Inventory::air_jumps`
+    pub air_jumps: u32,
     pub is_alive: bool,
+    /// Whether the player is a dynamic rigid body or uses the kinematic controller
+    pub body: CharacterBody,
 }
 
 // implement default()
 impl Default for Player {
     fn default() -> Self {
         Player {
+            index: 0,
             walk_speed: WALK_SPEED,
             jump_speed: JUMP_SPEED,
             is_jumping: false,
+            was_grounded: false,
+            air_jumps: 0,
             is_alive: true,
+            body: CharacterBody::default(),
         }
     }
 }
 
+/// Reads the Enum field `body` of the ldtk Player entity, `Dynamic` or `Kinematic`
+impl From<&EntityInstance> for Player {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let body = match fields::string_field(entity_instance, "body").as_deref() {
+            Some("Kinematic") => CharacterBody::Kinematic,
+            _ => CharacterBody::Dynamic,
+        };
+
+        Player { body, ..default() }
+    }
+}
+
 /// this is the bundle that will be instanced when the player entity is loaded from
 /// the ldtk level. further initialization will be done by the system `initialize_player`
 #[derive(Default, Bundle, LdtkEntity)]
 pub struct PlayerLdtkBundle {
+    #[from_entity_instance]
     player: Player,
+    actions: Actions,
     #[worldly]
     worldly: Worldly,
 }
 
+/// The sprite, animation and physics of a player
+fn player_components(
+    player: &Player,
+    transform: Transform,
+    player_walk: &PlayerWalkTextureAtlasAsset,
+) -> impl Bundle {
+    (
+        SpriteSheetBundle {
+            texture_atlas: player_walk.walking.clone(),
+            sprite: TextureAtlasSprite {
+                color: PLAYER_TINTS[player.index % PLAYER_TINTS.len()],
+                ..default()
+            },
+            transform,
+            ..default()
+        },
+        AnimationTimer {
+            timer: Timer::from_seconds(0.125, TimerMode::Repeating),
+            frame_count: 3,
+        },
+        Name::new(format!("player {}", player.index + 1)),
+        player.body.init_rigid_body(),
+    )
+}
+
 /// This is the system that will be called after the player is
 /// instanced from the ldtk level. The majority of the initialization
-/// takes place here.
+/// takes place here. In co-op the second player is spawned next to the first.
 fn initialize_player(
     mut commands: Commands,
-    query: Query<(Entity, &Transform), With<Player>>,
+    query: Query<
+        (
+            Entity,
+            &Transform,
+            &GlobalTransform,
+            &Player,
+            Option<&EntityInstance>,
+        ),
+        Added<Player>,
+    >,
     player_walk: Res<PlayerWalkTextureAtlasAsset>,
+    local_players: Res<LocalPlayers>,
+    mut checkpoint: ResMut<Checkpoint>,
     mut state: ResMut<NextState<GameState>>,
 ) {
-    let (entity, transform) = query.single();
+    // a reloaded or generated level may leave a stale player or a partner carrying the
+    // cloned ldtk entity behind, only the level's new first player is initialized
+    let players = query
+        .iter()
+        .filter(|(.., player, _)| player.index == 0)
+        .collect::<Vec<_>>();
+    let [(entity, transform, global_transform, player, entity_instance)] = players[..] else {
+        warn!(
+            "expected one new player in the level, found {}",
+            players.len()
+        );
+        state.set(GameState::InitializingPhysics);
+        return;
+    };
+
+    // extra lives respawn the player here until a checkpoint is reached
+    checkpoint.position = Some(global_transform.translation().truncate());
 
     commands
         .entity(entity)
-        .insert((
-            SpriteSheetBundle {
-                texture_atlas: player_walk.walking.clone(),
-                sprite: TextureAtlasSprite::default(),
-                transform: *transform,
-                ..default()
-            },
-            AnimationTimer {
-                timer: Timer::from_seconds(0.125, TimerMode::Repeating),
-                frame_count: 3,
-            },
-        ))
-        .insert(Name::new("player"))
-        .insert(physics::InitSpriteRigidBody::Dynamic);
+        .insert(player_components(player, *transform, &player_walk));
+
+    if player.body == CharacterBody::Kinematic {
+        commands
+            .entity(entity)
+            .insert(KinematicController::default());
+    }
+
+    // the partners aren't part of the level, so they are placed in world space
+    for index in 1..local_players.0 {
+        let partner = Player {
+            index,
+            body: player.body,
+            ..default()
+        };
+        let transform = Transform::from_translation(
+            global_transform.translation() + (PARTNER_SPAWN_OFFSET * index as f32).extend(0.0),
+        );
+
+        let mut partner_entity = commands.spawn((
+            player_components(&partner, transform, &player_walk),
+            Actions::default(),
+        ));
+
+        // the collider of the player is read from the ldtk entity
+        if let Some(entity_instance) = entity_instance {
+            partner_entity.insert(entity_instance.clone());
+        }
+        if partner.body == CharacterBody::Kinematic {
+            partner_entity.insert(KinematicController::default());
+        }
+
+        partner_entity.insert(partner);
+    }
 
     // After initializing the player, we need to setup the physics
     state.set(GameState::InitializingPhysics);
 }
 
 fn move_player(
-    actions: Res<Actions>,
-    mut player_velocity: Query<(&mut LinearVelocity, &mut Player)>,
-    player_collisions_query: Query<(&RayHits, &CollidingEntities), With<Player>>,
+    mut player_velocity: Query<(
+        &Actions,
+        &mut LinearVelocity,
+        &mut Player,
+        &GroundContacts,
+        &RayHits,
+        Option<&KinematicController>,
+        &WaterContacts,
+        &Position,
+        &ColliderAabb,
+    )>,
     grounds_query: Query<Entity, With<Ground>>,
+    inventory: Res<Inventory>,
 ) {
-    let (mut velocity, mut player) = player_velocity.single_mut();
-
-    // handle moving
-    if actions.player_movement.is_some() {
-        let movement = Vec2::new(
-            actions.player_movement.unwrap().x * player.walk_speed,
-            actions.player_movement.unwrap().y * player.walk_speed,
-        );
+    for (
+        actions,
+        mut velocity,
+        mut player,
+        ground_contacts,
+        hits,
+        controller,
+        water_contacts,
+        position,
+        aabb,
+    ) in player_velocity.iter_mut()
+    {
+        // handle moving
+        if actions.player_movement.is_some() {
+            let movement = Vec2::new(
+                actions.player_movement.unwrap().x * player.walk_speed,
+                actions.player_movement.unwrap().y * player.walk_speed,
+            );
 
-        velocity.x = movement.x;
-    }
+            velocity.x = movement.x;
+        }
 
-    // handle jumping
-    let is_grounded = physics::check_if_grounded(&player_collisions_query, &grounds_query);
-    if player.is_jumping {
+        // handle jumping
+        let is_grounded = match controller {
+            Some(controller) => controller.grounded,
+            None => ground_contacts.is_grounded(),
+        };
+        let was_grounded = std::mem::replace(&mut player.was_grounded, is_grounded);
         if is_grounded {
+            player.air_jumps = 0;
+        }
+        if player.is_jumping && is_grounded {
+            player.is_jumping = false;
+        } else if actions.jump && (is_grounded || player.air_jumps < inventory.air_jumps()) {
+            if !is_grounded {
+                player.air_jumps += 1;
+            }
+            player.is_jumping = true;
+            velocity.y = player.jump_speed;
+        }
+
+        // swim up while jump or up is held, sink slowly otherwise
+        if water_contacts.is_swimming() {
+            let swimming_up = actions.jump
+                || actions
+                    .player_movement
+                    .map_or(false, |movement| movement.y > 0.0);
+            velocity.y = if swimming_up { SWIM_SPEED } else { -SINK_SPEED };
             player.is_jumping = false;
+            player.air_jumps = 0;
+            continue;
+        }
+
+        // follow the ground when walking up or down slopes instead of launching off them.
+        // only a player that stood on the ground just now walks, anything else is falling.
+        // the kinematic controller does its own ground snapping. the rays start at the
+        // center, so they reach just past the feet of whatever collider the player has.
+        if controller.is_none() && !player.is_jumping && (is_grounded || was_grounded) {
+            if let Some(normal) = physics::ground_normal_below(
+                hits,
+                &grounds_query,
+                position.y - aabb.mins.y + GROUND_SNAP_DISTANCE,
+            ) {
+                velocity.y = -velocity.x * normal.x / normal.y;
+            }
         }
-    } else if actions.jump && is_grounded {
-        player.is_jumping = true;
-        velocity.y = player.jump_speed;
     }
 
     // screen_print!("is_grounded: {}", is_grounded);
@@ -124,15 +308,14 @@ fn move_player(
 }
 
 fn update_player_animation(
-    mut sprites: Query<(&mut TextureAtlasSprite, &mut AnimationTimer, With<Player>)>,
+    mut sprites: Query<(&mut TextureAtlasSprite, &mut AnimationTimer, &Actions), With<Player>>,
     time: Res<Time>,
-    actions: Res<Actions>,
 ) {
-    for (mut sprite, mut animation_timer, _) in &mut sprites {
+    for (mut sprite, mut animation_timer, actions) in &mut sprites {
         animation_timer.timer.tick(time.delta());
 
         if actions.player_movement.is_none() {
-            return;
+            continue;
         }
 
         match actions.player_movement {
@@ -157,8 +340,137 @@ fn cleanup(players: Query<(Entity, With<Player>)>, mut commands: Commands) {
     }
 }
 
-fn death_check(player: Query<&Player>, mut state: ResMut<NextState<GameState>>) {
-    if !player.single().is_alive {
-        state.set(GameState::PlayingCutScene);
+/// Ends the game when a player died, unless there is an extra life left. Then the
+/// player is respawned at the last checkpoint. Without lives, a player in co-op is
+/// respawned next to a partner who is still alive, and the game only ends when nobody is.
+fn death_check(
+    mut commands: Commands,
+    mut players: Query<(
+        Entity,
+        &mut Player,
+        &mut Position,
+        &mut LinearVelocity,
+        Option<&mut Interpolated>,
+    )>,
+    mut inventory: ResMut<Inventory>,
+    checkpoint: Res<Checkpoint>,
+    mut state: ResMut<NextState<GameState>>,
+    mut shakes: EventWriter<CameraShake>,
+    mut zoom_punches: EventWriter<ZoomPunch>,
+) {
+    let mut partner_position = players
+        .iter()
+        .find(|(_, player, ..)| player.is_alive)
+        .map(|(_, _, position, ..)| position.0);
+
+    for (entity, mut player, mut position, mut velocity, interpolated) in players.iter_mut() {
+        if player.is_alive {
+            continue;
+        }
+
+        shakes.send(CameraShake { trauma: 0.8 });
+        zoom_punches.send(ZoomPunch {
+            amount: 0.15,
+            seconds: 0.5,
+        });
+
+        let respawn = match checkpoint.position {
+            Some(checkpoint) if inventory.lives > 0 => {
+                inventory.lives -= 1;
+                Some(checkpoint)
+            }
+            _ => partner_position.map(|partner| partner + PARTNER_SPAWN_OFFSET),
+        };
+
+        let Some(respawn) = respawn else {
+            state.set(GameState::PlayingCutScene);
+            return;
+        };
+
+        player.is_alive = true;
+        position.0 = respawn;
+        velocity.0 = Vec2::ZERO;
+        commands.entity(entity).insert(HitFlash::default());
+        partner_position.get_or_insert(respawn);
+
+        // don't interpolate the jump to the respawn point
+        if let Some(mut interpolated) = interpolated {
+            *interpolated = Interpolated::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::InitSpriteRigidBody;
+    use bevy_ecs_ldtk::ldtk::{FieldInstance, FieldValue};
+
+    fn player_instance(body: &str) -> EntityInstance {
+        EntityInstance {
+            identifier: "Player".to_string(),
+            field_instances: vec![FieldInstance {
+                identifier: "body".to_string(),
+                tile: None,
+                field_instance_type: "LocalEnum.CharacterBody".to_string(),
+                value: FieldValue::Enum(Some(body.to_string())),
+                def_uid: 79,
+                real_editor_values: Vec::new(),
+            }],
+            ..default()
+        }
+    }
+
+    #[test]
+    fn body_is_read_from_ldtk() {
+        assert_eq!(
+            Player::from(&player_instance("Kinematic")).body,
+            CharacterBody::Kinematic
+        );
+        assert_eq!(
+            Player::from(&player_instance("Dynamic")).body,
+            CharacterBody::Dynamic
+        );
+        assert_eq!(
+            Player::from(&EntityInstance::default()).body,
+            CharacterBody::Dynamic
+        );
+    }
+
+    #[test]
+    fn kinematic_players_get_a_controller() {
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .insert_resource(LocalPlayers(2))
+            .init_resource::<Checkpoint>()
+            .insert_resource(PlayerWalkTextureAtlasAsset {
+                walking: Handle::default(),
+            })
+            .add_systems(Update, initialize_player);
+
+        let entity_instance = player_instance("Kinematic");
+        let player = app
+            .world
+            .spawn((
+                Player::from(&entity_instance),
+                entity_instance,
+                TransformBundle::default(),
+            ))
+            .id();
+
+        app.update();
+
+        let mut characters = app
+            .world
+            .query::<(Entity, &Player, &InitSpriteRigidBody, &KinematicController)>();
+        let characters = characters.iter(&app.world).collect::<Vec<_>>();
+
+        // the partner moves the same way as the player from the level
+        assert_eq!(characters.len(), 2);
+        assert!(characters.iter().any(|(entity, ..)| *entity == player));
+        assert!(characters.iter().all(|(_, player, rigid_body, _)| {
+            player.body == CharacterBody::Kinematic
+                && matches!(rigid_body, InitSpriteRigidBody::Kinematic)
+        }));
     }
 }
@@ -1,101 +1,385 @@
-// borrowed from https://github.com/alphastrata/shadplay
+// the shader library was borrowed from https://github.com/alphastrata/shadplay
 //!
-//! Most of the boilerplate to make a custom shader work lives here.
+//! The shader effects of the game: the shader library the background imports, effect
+//! materials for sprites and, in debug builds, previewing shaders that are dropped onto
+//! the window. Debug builds also reload changed `.wgsl` assets, see `asset_plugin`.
 //!
+//! The sprite effects are drawn by a quad on top of a `TextureAtlasSprite`, showing the
+//! same frame of the atlas. Inserting an effect component on a sprite adds the quad,
+//! removing it removes the quad again:
+//! - `HitFlash` flashes the sprite white and removes itself when it's over
+//! - `Outline` draws an outline around the sprite
+//! - `Dissolve` dissolves the sprite and despawns it when it's gone
 
-use std::path::PathBuf;
+use std::{marker::PhantomData, path::PathBuf};
 
 use bevy::{
     prelude::*,
     reflect::{TypePath, TypeUuid},
     render::render_resource::*,
-    sprite::Material2d,
+    sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle},
+    window::FileDragAndDrop,
 };
 
+use crate::GameState;
+
 pub mod common;
 
-/// Event: for facilitating the drag-n-drop of a .wgsl shader onto Shadplay.
+/// How long a `HitFlash` lasts
+const HIT_FLASH_SECONDS: f32 = 0.3;
+
+/// How long a `Dissolve` takes
+const DISSOLVE_SECONDS: f32 = 0.6;
+
+/// How far in front of the sprite its effect is drawn
+const EFFECT_Z: f32 = 0.01;
+
+pub struct ShaderEffectsPlugin;
+
+impl Plugin for ShaderEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            common::ShadplayShaderLibrary,
+            Material2dPlugin::<FlashMaterial>::default(),
+            Material2dPlugin::<OutlineMaterial>::default(),
+            Material2dPlugin::<DissolveMaterial>::default(),
+        ))
+        .add_event::<DragNDropShader>()
+        .add_systems(
+            Update,
+            (
+                tick_effects,
+                spawn_effect_overlays::<HitFlash>,
+                spawn_effect_overlays::<Outline>,
+                spawn_effect_overlays::<Dissolve>,
+                update_effect_overlays::<HitFlash>,
+                update_effect_overlays::<Outline>,
+                update_effect_overlays::<Dissolve>,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(OnExit(GameState::Playing), cleanup);
+
+        #[cfg(debug_assertions)]
+        app.add_systems(Update, (drop_shader_files, log_shader_reloads));
+    }
+}
+
+/// Event: a .wgsl shader was dropped onto the window, the background previews it.
 #[derive(Event, Debug, Deref, DerefMut)]
 pub struct DragNDropShader {
     pub path: PathBuf,
 }
 
-// ************************************ //
-//                3D                    //
-// ************************************ //
-/// The 3D shader.
-#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
-#[uuid = "a3d71c04-d054-4946-80f8-ba6cfbc90cad"]
-pub struct YourShader {
-    #[uniform(0)]
-    pub color: Color, //RGBA
+/// Flashes a sprite white, e.g. when it's hit
+#[derive(Component, Clone, Debug)]
+pub struct HitFlash {
+    pub timer: Timer,
+}
+
+impl Default for HitFlash {
+    fn default() -> Self {
+        HitFlash {
+            timer: Timer::from_seconds(HIT_FLASH_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+/// Outlines a sprite
+#[derive(Component, Clone, Debug)]
+pub struct Outline {
+    pub color: Color,
+    /// In texels of the sprite
+    pub thickness: f32,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Outline {
+            color: Color::WHITE,
+            thickness: 1.0,
+        }
+    }
+}
 
-    #[texture(1, dimension = "2d")]
-    #[sampler(2)]
-    pub img: Handle<Image>,
+/// Dissolves a sprite and despawns it when it's gone
+#[derive(Component, Clone, Debug)]
+pub struct Dissolve {
+    pub timer: Timer,
+    /// The color of the dissolving edge
+    pub color: Color,
 }
-// 3d impl
-impl Material for YourShader {
-    fn fragment_shader() -> ShaderRef {
-        "shaders/myshader.wgsl".into()
+
+impl Default for Dissolve {
+    fn default() -> Self {
+        Dissolve {
+            timer: Timer::from_seconds(DISSOLVE_SECONDS, TimerMode::Once),
+            color: Color::rgb(1.0, 0.6, 0.2),
+        }
     }
 }
 
-// ************************************ //
-//                2D                    //
-// ************************************ //
-/// The 2D shadertoy like shader
-#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
-#[uuid = "f528511f-dcf2-4b0b-9522-a9df3a1a795b"]
-pub struct YourShader2D {
-    #[uniform(0)]
-    pub(crate) mouse_pos: MousePos,
+/// A sprite effect, drawn with its material on top of the sprite
+pub trait SpriteEffect: Component {
+    type Material: SpriteEffectMaterial;
+
+    /// The color and the amount of the effect, see `SpriteEffectUniform`
+    fn color_and_amount(&self) -> (Color, f32);
 
-    #[texture(1, dimension = "2d")]
-    #[sampler(2)]
-    pub img: Handle<Image>,
+    /// Whether the sprite itself is hidden while the effect is drawn
+    fn hides_sprite(&self) -> bool {
+        false
+    }
 }
 
-#[derive(ShaderType, Debug, Clone)]
-pub struct MousePos {
-    pub x: f32,
-    pub y: f32,
+impl SpriteEffect for HitFlash {
+    type Material = FlashMaterial;
+
+    fn color_and_amount(&self) -> (Color, f32) {
+        (Color::WHITE, self.timer.percent_left())
+    }
 }
 
-impl Material2d for YourShader2D {
-    fn fragment_shader() -> ShaderRef {
-        "shaders/myshader_2d.wgsl".into()
+impl SpriteEffect for Outline {
+    type Material = OutlineMaterial;
+
+    fn color_and_amount(&self) -> (Color, f32) {
+        (self.color, self.thickness)
     }
 }
 
-// ---- ---- ---- ---- ---- ---- ---- ---- ----
-// For an example of how you can pass larger ammounts of data from bevy -> yourshadercode
-// You need to use this sort of structure, for example with the dotted_line.wgsl shader.
-// ---- ---- ---- ---- ---- ---- ---- ---- ----
-// dotted-line
-#[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
-#[uuid = "c74e039a-3df7-4f71-bd1d-7fe4b25a2230"]
-struct DottedLineShader {
-    #[uniform(0)]
-    uniforms: Holder, //RGBA
+impl SpriteEffect for Dissolve {
+    type Material = DissolveMaterial;
+
+    fn color_and_amount(&self) -> (Color, f32) {
+        (self.color, self.timer.percent())
+    }
+
+    fn hides_sprite(&self) -> bool {
+        true
+    }
 }
 
-/// Simplified holding struct to make passing across uniform(n) simpler.
-#[derive(ShaderType, Default, Clone, Debug)]
-struct Holder {
-    tint: Color,
-    /// How wide do you want the line as a % of its availablu uv space: 0.5 would be 50% of the surface of the geometry
-    line_width: f32,
-    /// How many segments (transparent 'cuts') do you want?
-    segments: f32,
-    /// How fast do you want the animation to be? set 0.0 to disable.
-    phase: f32,
-    /// How far spaced apart do you want these lines?
-    line_spacing: f32,
+/// Marks the quad that draws the effect `E` of its parent
+#[derive(Component)]
+struct EffectOverlay<E: SpriteEffect>(PhantomData<E>);
+
+fn tick_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut HitFlash)>,
+    mut dissolves: Query<(Entity, &mut Dissolve)>,
+) {
+    for (entity, mut flash) in flashes.iter_mut() {
+        if flash.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<HitFlash>();
+        }
+    }
+
+    for (entity, mut dissolve) in dissolves.iter_mut() {
+        if dissolve.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
 }
 
-impl Material for DottedLineShader {
-    fn fragment_shader() -> ShaderRef {
-        "shaders/dotted_line.wgsl".into()
+/// Sprites that are still dissolving when the game stops playing
+fn cleanup(mut commands: Commands, dissolves: Query<Entity, With<Dissolve>>) {
+    for entity in dissolves.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
+
+/// The uniform of the effect for the sprite's current frame
+fn effect_uniform<E: SpriteEffect>(
+    effect: &E,
+    atlas: &TextureAtlas,
+    sprite: &TextureAtlasSprite,
+) -> Option<SpriteEffectUniform> {
+    let rect = atlas.textures.get(sprite.index)?;
+    let (mut uv_min, mut uv_max) = (rect.min / atlas.size, rect.max / atlas.size);
+    if sprite.flip_x {
+        std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+    }
+    if sprite.flip_y {
+        std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+    }
+
+    let (color, amount) = effect.color_and_amount();
+
+    Some(SpriteEffectUniform {
+        uv_min,
+        uv_max,
+        color,
+        amount,
+    })
+}
+
+fn spawn_effect_overlays<E: SpriteEffect>(
+    mut commands: Commands,
+    mut effects: Query<(Entity, &E, &Handle<TextureAtlas>, &mut TextureAtlasSprite), Added<E>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<E::Material>>,
+) {
+    for (entity, effect, atlas, mut sprite) in effects.iter_mut() {
+        let Some(atlas) = atlases.get(atlas) else {
+            continue;
+        };
+        let Some(uniform) = effect_uniform(effect, atlas, &sprite) else {
+            continue;
+        };
+
+        let size = sprite
+            .custom_size
+            .unwrap_or_else(|| atlas.textures[sprite.index].size());
+        let overlay = commands
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(shape::Quad::new(size).into()).into(),
+                    material: materials.add(E::Material::new(atlas.texture.clone(), uniform)),
+                    transform: Transform::from_xyz(0.0, 0.0, EFFECT_Z),
+                    ..default()
+                },
+                EffectOverlay::<E>(PhantomData),
+                Name::new("effect"),
+            ))
+            .id();
+        commands.entity(entity).add_child(overlay);
+
+        if effect.hides_sprite() {
+            sprite.color.set_a(0.0);
+        }
+    }
+}
+
+/// Follows the frame of the sprite and the progress of the effect. Removes the quad
+/// once the effect is removed.
+fn update_effect_overlays<E: SpriteEffect>(
+    mut commands: Commands,
+    effects: Query<(&E, &Handle<TextureAtlas>, &TextureAtlasSprite)>,
+    overlays: Query<(Entity, &Parent, &Handle<E::Material>), With<EffectOverlay<E>>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<E::Material>>,
+) {
+    for (overlay, parent, material) in overlays.iter() {
+        let Ok((effect, atlas, sprite)) = effects.get(parent.get()) else {
+            commands.entity(overlay).despawn_recursive();
+            continue;
+        };
+
+        let uniform = atlases
+            .get(atlas)
+            .and_then(|atlas| effect_uniform(effect, atlas, sprite));
+
+        if let (Some(uniform), Some(material)) = (uniform, materials.get_mut(material)) {
+            *material.uniform_mut() = uniform;
+        }
+    }
+}
+
+/// Sends the `DragNDropShader` event for the shaders dropped onto the window
+#[cfg(debug_assertions)]
+fn drop_shader_files(
+    mut drag_and_drop: EventReader<FileDragAndDrop>,
+    mut dropped_shaders: EventWriter<DragNDropShader>,
+) {
+    for event in drag_and_drop.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            if path_buf
+                .extension()
+                .map_or(false, |extension| extension == "wgsl")
+            {
+                dropped_shaders.send(DragNDropShader {
+                    path: path_buf.clone(),
+                });
+            } else {
+                warn!("only .wgsl shaders can be dropped, not {:?}", path_buf);
+            }
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+fn log_shader_reloads(
+    mut shader_events: EventReader<AssetEvent<Shader>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in shader_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if let Some(path) = asset_server.get_handle_path(handle) {
+                info!("reloaded shader {:?}", path.path());
+            }
+        }
+    }
+}
+
+/// Where the sprite's frame is in its atlas, and the color and amount of the effect:
+/// the strength of a flash, the thickness of an outline in texels or the progress of a
+/// dissolve
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct SpriteEffectUniform {
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    pub color: Color,
+    pub amount: f32,
+}
+
+/// The material of a `SpriteEffect`, drawing a frame of a texture atlas
+pub trait SpriteEffectMaterial: Material2d {
+    fn new(texture: Handle<Image>, uniform: SpriteEffectUniform) -> Self;
+
+    fn uniform_mut(&mut self) -> &mut SpriteEffectUniform;
+}
+
+macro_rules! sprite_effect_material {
+    ($(#[$attr:meta])* $name:ident, $uuid:literal, $shader:literal) => {
+        $(#[$attr])*
+        #[derive(AsBindGroup, TypeUuid, TypePath, Debug, Clone)]
+        #[uuid = $uuid]
+        pub struct $name {
+            #[uniform(0)]
+            pub uniform: SpriteEffectUniform,
+            #[texture(1)]
+            #[sampler(2)]
+            pub texture: Handle<Image>,
+        }
+
+        impl Material2d for $name {
+            fn fragment_shader() -> ShaderRef {
+                $shader.into()
+            }
+        }
+
+        impl SpriteEffectMaterial for $name {
+            fn new(texture: Handle<Image>, uniform: SpriteEffectUniform) -> Self {
+                $name { uniform, texture }
+            }
+
+            fn uniform_mut(&mut self) -> &mut SpriteEffectUniform {
+                &mut self.uniform
+            }
+        }
+    };
+}
+
+sprite_effect_material!(
+    /// Draws the sprite in a color
+    FlashMaterial,
+    "2d4f6a8c-1e3b-4c5d-9f7a-0b2c4e6f8a13",
+    "shaders/effects/flash.wgsl"
+);
+
+sprite_effect_material!(
+    /// Draws an outline around the sprite
+    OutlineMaterial,
+    "6e8a0c2d-4f1b-4a3c-8d5e-7f9b1c3d5e24",
+    "shaders/effects/outline.wgsl"
+);
+
+sprite_effect_material!(
+    /// Draws the sprite with holes in it
+    DissolveMaterial,
+    "9a1c3e5f-7b2d-4e6f-a0c1-2d4e6f8a0b35",
+    "shaders/effects/dissolve.wgsl"
+);
@@ -0,0 +1,154 @@
+//! Colliders that match the art instead of guessed sizes. A collider is either authored
+//! in ldtk, as the Float array field `collider` holding the x, y pairs of a polygon in
+//! pixels relative to the center of the entity (y up), or generated as the convex hull
+//! of the opaque pixels of the entity's sprite. The hull is opt-in, with the `SpriteHull`
+//! component or the Bool field `sprite_hull` in ldtk, as it includes everything that's
+//! drawn, like hair or a weapon. Entities without either keep their own colliders.
+
+use crate::level::fields;
+use bevy::{
+    asset::HandleId, ecs::system::SystemParam, prelude::*, render::render_resource::TextureFormat,
+    utils::HashMap,
+};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+/// Pixels with at least this alpha are part of the collider
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Asks for a collider generated from the entity's sprite, see the module docs
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct SpriteHull;
+
+/// The convex hulls generated so far, so every texture is only scanned once
+#[derive(Resource, Default)]
+pub struct SpriteHullCache(HashMap<HandleId, Option<Collider>>);
+
+#[derive(SystemParam)]
+pub struct SpriteColliders<'w> {
+    images: Res<'w, Assets<Image>>,
+    atlases: Res<'w, Assets<TextureAtlas>>,
+    cache: ResMut<'w, SpriteHullCache>,
+}
+
+impl SpriteColliders<'_> {
+    /// The collider authored in ldtk if there is one, otherwise the convex hull of the
+    /// sprite if the entity opted in, either from the texture atlas or the image
+    pub fn collider(
+        &mut self,
+        entity_instance: Option<&EntityInstance>,
+        sprite_hull: Option<&SpriteHull>,
+        atlas: Option<&Handle<TextureAtlas>>,
+        image: Option<&Handle<Image>>,
+    ) -> Option<Collider> {
+        if let Some(collider) = entity_instance.and_then(authored_collider) {
+            return Some(collider);
+        }
+
+        let wants_hull = sprite_hull.is_some()
+            || entity_instance
+                .and_then(|entity_instance| fields::bool_field(entity_instance, "sprite_hull"))
+                .unwrap_or(false);
+        if !wants_hull {
+            return None;
+        }
+
+        atlas
+            .and_then(|atlas| self.atlas_hull(atlas))
+            .or_else(|| image.and_then(|image| self.image_hull(image)))
+    }
+
+    /// The convex hull of all the frames of the atlas, so the collider doesn't change
+    /// with the animation. Every frame's outline is in frame-local coordinates, so the
+    /// hull is as large as one sprite, not the whole sheet.
+    pub fn atlas_hull(&mut self, handle: &Handle<TextureAtlas>) -> Option<Collider> {
+        if let Some(hull) = self.cache.0.get(&handle.id()) {
+            return hull.clone();
+        }
+
+        let atlas = self.atlases.get(handle)?;
+        let image = self.images.get(&atlas.texture)?;
+
+        let points = atlas
+            .textures
+            .iter()
+            .flat_map(|rect| opaque_outline(image, *rect))
+            .collect::<Vec<_>>();
+        let hull = Collider::convex_hull(points);
+
+        self.cache.0.insert(handle.id(), hull.clone());
+        hull
+    }
+
+    pub fn image_hull(&mut self, handle: &Handle<Image>) -> Option<Collider> {
+        if let Some(hull) = self.cache.0.get(&handle.id()) {
+            return hull.clone();
+        }
+
+        let image = self.images.get(handle)?;
+        let rect = Rect::from_corners(Vec2::ZERO, image.size());
+        let hull = Collider::convex_hull(opaque_outline(image, rect));
+
+        self.cache.0.insert(handle.id(), hull.clone());
+        hull
+    }
+}
+
+/// Reads the polygon of the `collider` field. Needs at least three points.
+pub fn authored_collider(entity_instance: &EntityInstance) -> Option<Collider> {
+    let points = fields::floats_field(entity_instance, "collider")
+        .chunks_exact(2)
+        .map(|pair| Vec2::new(pair[0], pair[1]))
+        .collect::<Vec<_>>();
+
+    if points.len() < 3 {
+        return None;
+    }
+
+    Collider::convex_hull(points)
+}
+
+/// The corners of the leftmost and rightmost opaque pixel of every row of `rect`, in
+/// the coordinates of the frame: relative to the center of the rect with y up, no matter
+/// where the rect is in the image. The convex hull of these points is the convex hull of
+/// all opaque pixels.
+fn opaque_outline(image: &Image, rect: Rect) -> Vec<Vec2> {
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm
+    ) {
+        warn!(
+            "can't generate a collider from a {:?} image",
+            image.texture_descriptor.format
+        );
+        return Vec::new();
+    }
+
+    let width = image.texture_descriptor.size.width as usize;
+    let origin = rect.min.as_uvec2();
+    let size = rect.size().as_uvec2();
+    let half_size = rect.size() / 2.0;
+    let columns = 0..size.x as usize;
+    let mut points = Vec::new();
+
+    // x and y are relative to the top left corner of the frame
+    for y in 0..size.y as usize {
+        let row = (origin.y as usize + y) * width + origin.x as usize;
+        let opaque = |x: &usize| image.data[(row + x) * 4 + 3] >= ALPHA_THRESHOLD;
+
+        let (Some(first), Some(last)) = (
+            columns.clone().find(opaque),
+            columns.clone().rev().find(opaque),
+        ) else {
+            continue;
+        };
+
+        for x in [first as f32, last as f32 + 1.0] {
+            for y in [y as f32, y as f32 + 1.0] {
+                points.push(Vec2::new(x - half_size.x, half_size.y - y));
+            }
+        }
+    }
+
+    points
+}
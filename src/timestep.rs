@@ -0,0 +1,140 @@
+use crate::GameState;
+use bevy::{prelude::*, transform::TransformSystem, utils::Duration};
+use bevy_xpbd_2d::prelude::*;
+
+/// The default number of gameplay and physics ticks per second
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+
+pub struct TimestepPlugin;
+
+/// This plugin runs gameplay logic and physics on a fixed timestep in `FixedUpdate`,
+/// so the game behaves the same regardless of the frame rate. Rendering interpolates
+/// the transforms of physics bodies between the last two ticks.
+impl Plugin for TimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TickRate(DEFAULT_TICK_RATE))
+            .insert_resource(FixedTime::new_from_secs(1.0 / DEFAULT_TICK_RATE as f32))
+            .init_resource::<SimulationTime>()
+            .configure_set(
+                FixedUpdate,
+                FixedGameplaySet
+                    .before(PhysicsSet::Prepare)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                apply_tick_rate.run_if(resource_changed::<TickRate>()),
+            )
+            .add_systems(
+                FixedUpdate,
+                (advance_simulation_time, restore_physics_transforms)
+                    .before(FixedGameplaySet)
+                    .before(PhysicsSet::Prepare),
+            )
+            .add_systems(
+                FixedUpdate,
+                record_physics_positions.after(PhysicsSet::Sync),
+            )
+            .add_systems(
+                PostUpdate,
+                interpolate_transforms.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// The gameplay systems that run once per fixed tick, before the physics step
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FixedGameplaySet;
+
+/// The number of gameplay and physics ticks per second. Change it at runtime to change
+/// the tick rate of both.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TickRate(pub f64);
+
+/// The clock of the simulation. Systems in `FixedUpdate` use this instead of `Time`,
+/// which still measures the variable frame time there.
+#[derive(Resource, Default, Debug)]
+pub struct SimulationTime {
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl SimulationTime {
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn delta_seconds_f64(&self) -> f64 {
+        self.delta.as_secs_f64()
+    }
+
+    pub fn elapsed_seconds_f64(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+}
+
+/// Added to physics bodies whose sprite should be interpolated between ticks. Holds the
+/// translations of the last two ticks.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Interpolated {
+    pub previous: Option<Vec2>,
+    pub current: Option<Vec2>,
+}
+
+fn apply_tick_rate(
+    tick_rate: Res<TickRate>,
+    mut fixed_time: ResMut<FixedTime>,
+    mut physics_timestep: ResMut<PhysicsTimestep>,
+) {
+    let period = 1.0 / tick_rate.0;
+
+    fixed_time.period = Duration::from_secs_f64(period);
+    *physics_timestep = PhysicsTimestep::FixedOnce(period as f32);
+}
+
+fn advance_simulation_time(fixed_time: Res<FixedTime>, mut time: ResMut<SimulationTime>) {
+    time.delta = fixed_time.period;
+    time.elapsed += fixed_time.period;
+}
+
+/// Undoes the render interpolation before the tick, so the physics step starts from the
+/// simulated positions
+fn restore_physics_transforms(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in query.iter_mut() {
+        if let Some(current) = interpolated.current {
+            transform.translation.x = current.x;
+            transform.translation.y = current.y;
+        }
+    }
+}
+
+/// Records the transforms the physics step synced from the body positions. Transforms
+/// are used rather than `Position` so that bodies nested in the level work as well.
+fn record_physics_positions(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in query.iter_mut() {
+        let translation = transform.translation.truncate();
+
+        interpolated.previous = interpolated.current.or(Some(translation));
+        interpolated.current = Some(translation);
+    }
+}
+
+pub fn interpolate_transforms(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+    let alpha =
+        (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).clamp(0.0, 1.0);
+
+    for (mut transform, interpolated) in query.iter_mut() {
+        if let (Some(previous), Some(current)) = (interpolated.previous, interpolated.current) {
+            let position = previous.lerp(current, alpha);
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+}